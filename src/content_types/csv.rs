@@ -0,0 +1,323 @@
+//! CSV/TSV export for GPS, sensor data, and generic data streams.
+//!
+//! `Gps`, `Imu` and `TimedSamples` (any `STRM`, see `Gpmf::timed_samples()`)
+//! implement `ToCsv`. Columns are configured via `CsvOptions`.
+//! For multi-clip recording sessions, see `GoProSession::to_csv()`.
+//!
+//! ```rs
+//! use gpmf_rs::{CsvColumn, CsvOptions, FourCC, Gpmf, ToCsv};
+//! use std::path::Path;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! let options = CsvOptions {
+//!     columns: vec![CsvColumn::Time, CsvColumn::Utc, CsvColumn::Values],
+//!     time_model: gpmf.time_model().ok(),
+//!     ..CsvOptions::tsv()
+//! };
+//! for accl in gpmf.timed_samples(&FourCC::ACCL) {
+//!     print!("{}", accl.to_csv(&options));
+//! }
+//! ```
+
+use std::path::Path;
+
+use time::{Duration, OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::{GpmfError, Gps, Imu, TimeModel, TimedSamples, files::write_new};
+
+/// Column in CSV output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// Time in seconds, relative to video start.
+    Time,
+    /// Absolute time (UTC, RFC3339).
+    /// Derived from `CsvOptions::time_model` if set,
+    /// otherwise GPS datetime for `Gps`, empty for other data.
+    Utc,
+    /// Values, one column per value, with units in header
+    /// (`SIUN` if logged, otherwise `UNIT`).
+    Values,
+    /// Source file.
+    Source,
+}
+
+/// Options for CSV/TSV output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Field delimiter, `,` for CSV, `\t` for TSV.
+    pub delimiter: char,
+    /// Columns, in output order.
+    pub columns: Vec<CsvColumn>,
+    /// Include header row.
+    pub header: bool,
+    /// Time model for `CsvColumn::Utc`. See `Gpmf::time_model()`.
+    pub time_model: Option<TimeModel>,
+    /// Source file for `CsvColumn::Source`.
+    pub source: Option<String>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            columns: vec![CsvColumn::Time, CsvColumn::Values],
+            header: true,
+            time_model: None,
+            source: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Options for tab separated values.
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: '\t',
+            ..Self::default()
+        }
+    }
+
+    /// Options with all columns.
+    pub fn all() -> Self {
+        Self {
+            columns: vec![CsvColumn::Time, CsvColumn::Utc, CsvColumn::Values, CsvColumn::Source],
+            ..Self::default()
+        }
+    }
+}
+
+/// Single row of values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRow {
+    /// Time relative to video start.
+    pub time: Duration,
+    /// Absolute time, if known by the data itself (e.g. GPS datetime).
+    pub utc: Option<OffsetDateTime>,
+    pub values: Vec<String>,
+}
+
+/// Export to CSV/TSV.
+pub trait ToCsv {
+    /// Value column headers, with units.
+    fn csv_headers(&self) -> Vec<String>;
+
+    /// Rows, ordered by time.
+    fn csv_rows(&self) -> Vec<CsvRow>;
+
+    /// Returns data as CSV/TSV.
+    fn to_csv(&self, options: &CsvOptions) -> String {
+        csv_string(&self.csv_headers(), &self.csv_rows(), options)
+    }
+
+    /// Write CSV/TSV file.
+    fn write_csv(&self, path: &Path, options: &CsvOptions) -> Result<(), GpmfError> {
+        write_new(path, self.to_csv(options).as_bytes())
+    }
+}
+
+/// Quotes field if it contains delimiter, quotes, or line breaks.
+fn csv_field(field: &str, delimiter: char) -> String {
+    match field.contains([delimiter, '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+/// Returns rows as CSV/TSV with columns set in `options`.
+pub(crate) fn csv_string(headers: &[String], rows: &[CsvRow], options: &CsvOptions) -> String {
+    let delimiter = options.delimiter;
+    let join = |fields: Vec<String>| -> String {
+        let mut line = fields.iter()
+            .map(|f| csv_field(f, delimiter))
+            .collect::<Vec<_>>()
+            .join(&delimiter.to_string());
+        line.push('\n');
+        line
+    };
+
+    let mut csv = String::new();
+
+    if options.header {
+        csv.push_str(&join(options.columns.iter()
+            .flat_map(|c| match c {
+                CsvColumn::Time => vec!["time (s)".to_owned()],
+                CsvColumn::Utc => vec!["utc".to_owned()],
+                CsvColumn::Values => headers.to_vec(),
+                CsvColumn::Source => vec!["source".to_owned()],
+            })
+            .collect()));
+    }
+
+    for row in rows.iter() {
+        csv.push_str(&join(options.columns.iter()
+            .flat_map(|c| match c {
+                CsvColumn::Time => vec![format!("{:.6}", row.time.as_seconds_f64())],
+                CsvColumn::Utc => vec![options.time_model.as_ref()
+                    .map(|m| m.utc(row.time))
+                    .or(row.utc)
+                    .and_then(|dt| dt.format(&Rfc3339).ok())
+                    .unwrap_or_default()],
+                CsvColumn::Values => row.values.to_owned(),
+                CsvColumn::Source => vec![options.source.to_owned().unwrap_or_default()],
+            })
+            .collect()));
+    }
+
+    csv
+}
+
+impl ToCsv for Gps {
+    fn csv_headers(&self) -> Vec<String> {
+        [
+            "latitude (deg)",
+            "longitude (deg)",
+            "altitude (m)",
+            "speed2d (m/s)",
+            "speed3d (m/s)",
+            "dop",
+            "fix",
+        ].iter()
+            .map(|h| h.to_string())
+            .collect()
+    }
+
+    fn csv_rows(&self) -> Vec<CsvRow> {
+        self.iter()
+            .map(|p| CsvRow {
+                time: p.timestamp,
+                utc: p.datetime().ok(),
+                values: vec![
+                    p.latitude().to_string(),
+                    p.longitude().to_string(),
+                    p.altitude().to_string(),
+                    p.speed2d.to_string(),
+                    p.speed3d.to_string(),
+                    p.dop.to_string(),
+                    p.fix.to_string(),
+                ],
+            })
+            .collect()
+    }
+}
+
+impl ToCsv for Imu {
+    fn csv_headers(&self) -> Vec<String> {
+        let units = self.units.to_owned()
+            .unwrap_or_else(|| self.sensor.units().to_owned());
+        ["x", "y", "z"].iter()
+            .map(|axis| format!("{axis} ({units})"))
+            .collect()
+    }
+
+    /// Sample times as in `Imu::sample_offsets()`.
+    /// If `timestamp` or `duration` is not set,
+    /// the sample index is used as time in seconds,
+    /// so that no samples are dropped.
+    fn csv_rows(&self) -> Vec<CsvRow> {
+        let mut offsets = self.sample_offsets();
+        if offsets.len() != self.samples.len() {
+            offsets = (0..self.samples.len()).map(|i| i as f64).collect();
+        }
+        self.samples.iter()
+            .zip(offsets)
+            .map(|(s, t)| CsvRow {
+                time: Duration::seconds_f64(t),
+                utc: None,
+                values: vec![s.x.to_string(), s.y.to_string(), s.z.to_string()],
+            })
+            .collect()
+    }
+}
+
+/// For sensor data as returned by `Gpmf::imu()`,
+/// i.e. one `Imu` per `DEVC`.
+impl ToCsv for Vec<Imu> {
+    fn csv_headers(&self) -> Vec<String> {
+        self.first()
+            .map(|imu| imu.csv_headers())
+            .unwrap_or_default()
+    }
+
+    fn csv_rows(&self) -> Vec<CsvRow> {
+        self.iter()
+            .flat_map(|imu| imu.csv_rows())
+            .collect()
+    }
+}
+
+impl ToCsv for TimedSamples {
+    fn csv_headers(&self) -> Vec<String> {
+        let width = self.samples.iter()
+            .map(|s| s.values.len())
+            .max()
+            .unwrap_or(0);
+        let fourcc = self.fourcc.to_str();
+        (0..width)
+            .map(|i| {
+                // Single unit applies to all values
+                let unit = self.units.as_ref()
+                    .and_then(|u| u.get(i).or(u.first().filter(|_| u.len() == 1)));
+                match unit {
+                    Some(unit) if !unit.is_empty() => format!("{fourcc} {} ({unit})", i + 1),
+                    _ => format!("{fourcc} {}", i + 1),
+                }
+            })
+            .collect()
+    }
+
+    fn csv_rows(&self) -> Vec<CsvRow> {
+        self.iter()
+            .map(|s| CsvRow {
+                time: s.time,
+                utc: None,
+                values: s.values.iter().map(|v| v.to_string()).collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use crate::{Imu, ImuSample};
+
+    use super::{CsvOptions, ToCsv};
+
+    fn imu(timestamp: Option<f64>, duration: Option<f64>) -> Imu {
+        Imu {
+            samples: (0..4)
+                .map(|i| ImuSample { x: i as f64, y: 0.5, z: -1.0 })
+                .collect(),
+            timestamp: timestamp.map(Duration::seconds_f64),
+            duration: duration.map(Duration::seconds_f64),
+            ..Imu::default()
+        }
+    }
+
+    fn times(imu: &Imu) -> Vec<f64> {
+        imu.csv_rows().iter()
+            .map(|r| r.time.as_seconds_f64())
+            .collect()
+    }
+
+    #[test]
+    fn imu_rows_timed() {
+        let imu = imu(Some(2.0), Some(1.0));
+        assert_eq!(times(&imu), vec![2.0, 2.25, 2.5, 2.75]);
+
+        let csv = imu.to_csv(&CsvOptions::default());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], "2.000000,0,0.5,-1");
+        assert_eq!(lines[4], "2.750000,3,0.5,-1");
+    }
+
+    #[test]
+    fn imu_rows_untimed() {
+        for imu in [imu(None, None), imu(Some(2.0), None), imu(None, Some(1.0))] {
+            assert_eq!(times(&imu), vec![0.0, 1.0, 2.0, 3.0]);
+            assert_eq!(imu.to_csv(&CsvOptions::default()).lines().count(), 5);
+        }
+    }
+}
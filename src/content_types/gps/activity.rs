@@ -0,0 +1,530 @@
+//! Activity export (Garmin FIT and TCX) for GPS logs,
+//! for import into training platforms.
+//!
+//! Records (FIT) and track points (TCX) carry datetime, position,
+//! altitude, 2D speed and cumulative distance.
+//! Each `Gps` is exported as a single lap. For recording sessions,
+//! one lap is created per clip (see `GoProSession::to_fit()`).
+//!
+//! Points are ordered by datetime, which is required for both formats.
+//! Points without a valid datetime, including the default datetime
+//! GoPro logs before the first satellite lock (2000-01-01),
+//! and points without at least a 2D satellite lock are skipped,
+//! since these would otherwise add to total distance.
+//! Use `Gps::prune()` first to also exclude points with poor precision (DOP).
+
+use std::path::Path;
+
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::{GpmfError, files::write_new};
+use super::{GoProPoint, Gps};
+
+/// Seconds between Unix epoch and FIT epoch (1989-12-31T00:00:00Z).
+const FIT_EPOCH_OFFSET: i64 = 631_065_600;
+
+/// Activity type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sport {
+    #[default]
+    Generic,
+    Running,
+    Cycling,
+}
+
+impl Sport {
+    /// FIT `sport` enum value.
+    fn fit(&self) -> u8 {
+        match self {
+            Sport::Generic => 0,
+            Sport::Running => 1,
+            Sport::Cycling => 2,
+        }
+    }
+
+    /// TCX `Sport` attribute value.
+    fn tcx(&self) -> &'static str {
+        match self {
+            Sport::Generic => "Other",
+            Sport::Running => "Running",
+            Sport::Cycling => "Biking",
+        }
+    }
+}
+
+/// Point with datetime and cumulative distance in meters
+/// since activity start.
+struct TrackPoint<'a> {
+    point: &'a GoProPoint,
+    datetime: OffsetDateTime,
+    distance: f64,
+}
+
+/// Lap summary.
+struct Lap<'a> {
+    points: Vec<TrackPoint<'a>>,
+}
+
+impl Lap<'_> {
+    fn start(&self) -> Option<&TrackPoint<'_>> {
+        self.points.first()
+    }
+
+    fn end(&self) -> Option<&TrackPoint<'_>> {
+        self.points.last()
+    }
+
+    /// Elapsed time in seconds.
+    fn elapsed(&self) -> f64 {
+        match (self.start(), self.end()) {
+            (Some(a), Some(b)) => (b.datetime - a.datetime).as_seconds_f64(),
+            _ => 0.,
+        }
+    }
+
+    /// Lap distance in meters.
+    fn distance(&self) -> f64 {
+        match (self.start(), self.end()) {
+            (Some(a), Some(b)) => b.distance - a.distance,
+            _ => 0.,
+        }
+    }
+
+    fn max_speed(&self) -> f64 {
+        self.points.iter()
+            .map(|p| p.point.speed2d)
+            .fold(0., f64::max)
+    }
+
+    fn avg_speed(&self) -> f64 {
+        match self.elapsed() > 0. {
+            true => self.distance() / self.elapsed(),
+            false => 0.,
+        }
+    }
+}
+
+/// Returns one lap per `Gps`, with points ordered by datetime,
+/// and distance accumulated across laps.
+/// Skips points without a valid datetime or satellite lock.
+fn laps(gps: &[Gps]) -> Vec<Lap<'_>> {
+    let mut distance = 0.;
+    let mut previous: Option<&GoProPoint> = None;
+    gps.iter()
+        .map(|g| {
+            let mut points: Vec<(&GoProPoint, OffsetDateTime)> = g.iter()
+                .filter(|p| p.fix >= 2)
+                .filter_map(|p| Some((p, p.datetime().ok()?)))
+                // Default datetime logged before a satellite lock
+                .filter(|(_, dt)| *dt > GoProPoint::datetime_default())
+                .collect();
+            points.sort_by_key(|(_, dt)| *dt);
+            Lap {
+                points: points.into_iter()
+                    .map(|(point, datetime)| {
+                        if let Some(prev) = previous {
+                            distance += prev.haversine(point);
+                        }
+                        previous = Some(point);
+                        TrackPoint { point, datetime, distance }
+                    })
+                    .collect()
+            }
+        })
+        .filter(|lap| !lap.points.is_empty())
+        .collect()
+}
+
+// FIT
+
+/// FIT field value, with field definition number.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Enum(u8, u8),
+    Uint16(u8, u16),
+    Uint32(u8, u32),
+    Uint32z(u8, u32),
+    Sint32(u8, i32),
+}
+
+impl Field {
+    /// Field definition number, size in bytes, base type.
+    fn definition(&self) -> [u8; 3] {
+        match self {
+            Field::Enum(n, _) => [*n, 1, 0x00],
+            Field::Uint16(n, _) => [*n, 2, 0x84],
+            Field::Uint32(n, _) => [*n, 4, 0x86],
+            Field::Uint32z(n, _) => [*n, 4, 0x8C],
+            Field::Sint32(n, _) => [*n, 4, 0x85],
+        }
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        match self {
+            Field::Enum(_, v) => buf.push(*v),
+            Field::Uint16(_, v) => buf.extend(v.to_le_bytes()),
+            Field::Uint32(_, v) | Field::Uint32z(_, v) => buf.extend(v.to_le_bytes()),
+            Field::Sint32(_, v) => buf.extend(v.to_le_bytes()),
+        }
+    }
+}
+
+/// FIT CRC-16, as specified in the FIT SDK.
+fn fit_crc(crc: u16, bytes: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401,
+        0xA001, 0x6C00, 0x7800, 0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    bytes.iter().fold(crc, |mut crc, byte| {
+        let tmp = TABLE[(crc & 0xF) as usize];
+        crc = ((crc >> 4) & 0x0FFF) ^ tmp ^ TABLE[(byte & 0xF) as usize];
+        let tmp = TABLE[(crc & 0xF) as usize];
+        ((crc >> 4) & 0x0FFF) ^ tmp ^ TABLE[((byte >> 4) & 0xF) as usize]
+    })
+}
+
+/// Writes FIT messages. A definition message is written
+/// whenever the layout for a local message type changes.
+#[derive(Default)]
+struct FitWriter {
+    data: Vec<u8>,
+    definitions: Vec<Option<(u16, Vec<[u8; 3]>)>>,
+}
+
+impl FitWriter {
+    fn message(&mut self, local: u8, global: u16, fields: &[Field]) {
+        let definition = (global, fields.iter().map(|f| f.definition()).collect::<Vec<_>>());
+        let index = local as usize;
+        if self.definitions.len() <= index {
+            self.definitions.resize(index + 1, None);
+        }
+        if self.definitions[index].as_ref() != Some(&definition) {
+            // Definition header, reserved, little endian
+            self.data.extend([0x40 | local, 0, 0]);
+            self.data.extend(global.to_le_bytes());
+            self.data.push(fields.len() as u8);
+            definition.1.iter().for_each(|d| self.data.extend(d));
+            self.definitions[index] = Some(definition);
+        }
+        self.data.push(local);
+        fields.iter().for_each(|f| f.write(&mut self.data));
+    }
+
+    /// Returns complete FIT file, with file header and CRC.
+    fn finish(self) -> Vec<u8> {
+        let mut fit = Vec::with_capacity(self.data.len() + 16);
+        fit.push(14); // header size
+        fit.push(0x20); // protocol version 2.0
+        fit.extend(2132_u16.to_le_bytes()); // profile version 21.32
+        fit.extend((self.data.len() as u32).to_le_bytes());
+        fit.extend(b".FIT");
+        let header_crc = fit_crc(0, &fit);
+        fit.extend(header_crc.to_le_bytes());
+        fit.extend(self.data);
+        let crc = fit_crc(0, &fit);
+        fit.extend(crc.to_le_bytes());
+        fit
+    }
+}
+
+fn fit_time(datetime: &OffsetDateTime) -> u32 {
+    (datetime.unix_timestamp() - FIT_EPOCH_OFFSET).max(0) as u32
+}
+
+/// Degrees to semicircles.
+fn fit_semicircles(degrees: f64) -> i32 {
+    (degrees * (2_f64.powi(31) / 180.)).round() as i32
+}
+
+/// Scales value, clamped to type range.
+fn fit_scaled(value: f64, scale: f64, offset: f64, max: f64) -> f64 {
+    ((value + offset) * scale).round().clamp(0., max)
+}
+
+/// Returns laps as a FIT activity file.
+pub(crate) fn fit_activity(gps: &[Gps], sport: Sport) -> Vec<u8> {
+    let laps = laps(gps);
+    let mut fit = FitWriter::default();
+
+    let (Some(first), Some(last)) = (
+        laps.first().and_then(|l| l.start()),
+        laps.last().and_then(|l| l.end())
+    ) else {
+        return fit.finish()
+    };
+    let (start, end) = (fit_time(&first.datetime), fit_time(&last.datetime));
+
+    // file_id: activity, development manufacturer
+    fit.message(0, 0, &[
+        Field::Enum(0, 4),
+        Field::Uint16(1, 255),
+        Field::Uint16(2, 0),
+        Field::Uint32z(3, 1),
+        Field::Uint32(4, start),
+    ]);
+
+    // event: timer start
+    fit.message(1, 21, &[
+        Field::Uint32(253, start),
+        Field::Enum(0, 0),
+        Field::Enum(1, 0),
+    ]);
+
+    for lap in laps.iter() {
+        for p in lap.points.iter() {
+            // record
+            fit.message(2, 20, &[
+                Field::Uint32(253, fit_time(&p.datetime)),
+                Field::Sint32(0, fit_semicircles(p.point.latitude())),
+                Field::Sint32(1, fit_semicircles(p.point.longitude())),
+                Field::Uint32(5, fit_scaled(p.distance, 100., 0., u32::MAX as f64 - 1.) as u32),
+                Field::Uint32(73, fit_scaled(p.point.speed2d, 1000., 0., u32::MAX as f64 - 1.) as u32),
+                Field::Uint32(78, fit_scaled(p.point.altitude(), 5., 500., u32::MAX as f64 - 1.) as u32),
+            ]);
+        }
+    }
+
+    // event: timer stop all
+    fit.message(1, 21, &[
+        Field::Uint32(253, end),
+        Field::Enum(0, 0),
+        Field::Enum(1, 4),
+    ]);
+
+    for (i, lap) in laps.iter().enumerate() {
+        let (Some(a), Some(b)) = (lap.start(), lap.end()) else {
+            continue
+        };
+        let elapsed = fit_scaled(lap.elapsed(), 1000., 0., u32::MAX as f64 - 1.) as u32;
+        // lap
+        fit.message(3, 19, &[
+            Field::Uint32(253, fit_time(&b.datetime)),
+            Field::Uint16(254, i as u16),
+            Field::Enum(0, 9),
+            Field::Enum(1, 1),
+            Field::Uint32(2, fit_time(&a.datetime)),
+            Field::Sint32(3, fit_semicircles(a.point.latitude())),
+            Field::Sint32(4, fit_semicircles(a.point.longitude())),
+            Field::Sint32(5, fit_semicircles(b.point.latitude())),
+            Field::Sint32(6, fit_semicircles(b.point.longitude())),
+            Field::Uint32(7, elapsed),
+            Field::Uint32(8, elapsed),
+            Field::Uint32(9, fit_scaled(lap.distance(), 100., 0., u32::MAX as f64 - 1.) as u32),
+            Field::Uint32(110, fit_scaled(lap.avg_speed(), 1000., 0., u32::MAX as f64 - 1.) as u32),
+            Field::Uint32(111, fit_scaled(lap.max_speed(), 1000., 0., u32::MAX as f64 - 1.) as u32),
+            Field::Enum(25, sport.fit()),
+        ]);
+    }
+
+    let elapsed_seconds = (last.datetime - first.datetime).as_seconds_f64();
+    let elapsed = fit_scaled(elapsed_seconds, 1000., 0., u32::MAX as f64 - 1.) as u32;
+    let avg_speed = match elapsed_seconds > 0. {
+        true => last.distance / elapsed_seconds,
+        false => 0.,
+    };
+    let max_speed = laps.iter().map(|l| l.max_speed()).fold(0., f64::max);
+
+    // session
+    fit.message(4, 18, &[
+        Field::Uint32(253, end),
+        Field::Uint16(254, 0),
+        Field::Enum(0, 8),
+        Field::Enum(1, 1),
+        Field::Uint32(2, start),
+        Field::Sint32(3, fit_semicircles(first.point.latitude())),
+        Field::Sint32(4, fit_semicircles(first.point.longitude())),
+        Field::Enum(5, sport.fit()),
+        Field::Enum(6, 0),
+        Field::Uint32(7, elapsed),
+        Field::Uint32(8, elapsed),
+        Field::Uint32(9, fit_scaled(last.distance, 100., 0., u32::MAX as f64 - 1.) as u32),
+        Field::Uint32(124, fit_scaled(avg_speed, 1000., 0., u32::MAX as f64 - 1.) as u32),
+        Field::Uint32(125, fit_scaled(max_speed, 1000., 0., u32::MAX as f64 - 1.) as u32),
+        Field::Uint16(25, 0),
+        Field::Uint16(26, laps.len() as u16),
+    ]);
+
+    // activity
+    fit.message(5, 34, &[
+        Field::Uint32(253, end),
+        Field::Uint32(0, elapsed),
+        Field::Uint16(1, 1),
+        Field::Enum(2, 0),
+        Field::Enum(3, 26),
+        Field::Enum(4, 1),
+    ]);
+
+    fit.finish()
+}
+
+// TCX
+
+/// Returns laps as a TCX document.
+pub(crate) fn tcx_activity(gps: &[Gps], sport: Sport) -> String {
+    let laps = laps(gps);
+    let format = |dt: &OffsetDateTime| dt.format(&Rfc3339).unwrap_or_default();
+
+    let id = laps.first()
+        .and_then(|l| l.start())
+        .map(|p| format(&p.datetime))
+        .unwrap_or_default();
+
+    let laps = laps.iter()
+        .filter_map(|lap| {
+            let start = lap.start()?;
+            let points = lap.points.iter()
+                .map(|p| format!(concat!(
+                    "<Trackpoint>",
+                    "<Time>{time}</Time>",
+                    "<Position><LatitudeDegrees>{lat}</LatitudeDegrees><LongitudeDegrees>{lon}</LongitudeDegrees></Position>",
+                    "<AltitudeMeters>{alt}</AltitudeMeters>",
+                    "<DistanceMeters>{dist:.2}</DistanceMeters>",
+                    "<Extensions><ns3:TPX><ns3:Speed>{speed}</ns3:Speed></ns3:TPX></Extensions>",
+                    "</Trackpoint>\n"),
+                    time = format(&p.datetime),
+                    lat = p.point.latitude(),
+                    lon = p.point.longitude(),
+                    alt = p.point.altitude(),
+                    dist = p.distance,
+                    speed = p.point.speed2d,
+                ))
+                .collect::<String>();
+            Some(format!(concat!(
+                "<Lap StartTime=\"{start}\">\n",
+                "<TotalTimeSeconds>{time:.3}</TotalTimeSeconds>\n",
+                "<DistanceMeters>{dist:.2}</DistanceMeters>\n",
+                "<MaximumSpeed>{max}</MaximumSpeed>\n",
+                "<Calories>0</Calories>\n",
+                "<Intensity>Active</Intensity>\n",
+                "<TriggerMethod>Manual</TriggerMethod>\n",
+                "<Track>\n{points}</Track>\n",
+                "</Lap>\n"),
+                start = format(&start.datetime),
+                time = lap.elapsed(),
+                dist = lap.distance(),
+                max = lap.max_speed(),
+                points = points,
+            ))
+        })
+        .collect::<String>();
+
+    format!(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\" ",
+        "xmlns:ns3=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\">\n",
+        "<Activities>\n",
+        "<Activity Sport=\"{sport}\">\n",
+        "<Id>{id}</Id>\n",
+        "{laps}",
+        "</Activity>\n",
+        "</Activities>\n",
+        "</TrainingCenterDatabase>\n"),
+        sport = sport.tcx(), id = id, laps = laps
+    )
+}
+
+impl Gps {
+    /// Returns GPS log as a FIT activity file, as a single lap.
+    /// Records carry datetime, position, altitude, 2D speed,
+    /// and cumulative distance.
+    /// Points without a valid datetime or without at least
+    /// a 2D satellite lock are skipped.
+    pub fn to_fit(&self, sport: Sport) -> Vec<u8> {
+        fit_activity(std::slice::from_ref(self), sport)
+    }
+
+    /// Write FIT activity file.
+    pub fn write_fit(&self, path: &Path, sport: Sport) -> Result<(), GpmfError> {
+        write_new(path, &self.to_fit(sport))
+    }
+
+    /// Returns GPS log as a TCX document, as a single lap.
+    /// Points are skipped as for `Gps::to_fit()`.
+    pub fn to_tcx(&self, sport: Sport) -> String {
+        tcx_activity(std::slice::from_ref(self), sport)
+    }
+
+    /// Write TCX file.
+    pub fn write_tcx(&self, path: &Path, sport: Sport) -> Result<(), GpmfError> {
+        write_new(path, self.to_tcx(sport).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::{fit_crc, laps};
+    use crate::{GoProPoint, Gps, Sport};
+
+    /// Degrees latitude per meter.
+    const DEG_PER_M: f64 = 1. / 111_195.;
+
+    /// Point with 3D lock, `seconds` after 2020-01-01
+    /// and `north` meters from the equator.
+    fn point(seconds: i64, north: f64) -> GoProPoint {
+        let mut p = GoProPoint {
+            speed2d: 1.,
+            fix: 3,
+            ..GoProPoint::default()
+        }.with_lat_lon(north * DEG_PER_M, 0.);
+        p.add_duration(Duration::days(7305) + Duration::seconds(seconds));
+        p
+    }
+
+    #[test]
+    fn fit_crc_check_value() {
+        // FIT SDK CRC is CRC-16/ARC, check value for "123456789"
+        assert_eq!(fit_crc(0, b"123456789"), 0xBB3D);
+        assert_eq!(fit_crc(0, b""), 0);
+        // Chained
+        assert_eq!(fit_crc(fit_crc(0, b"1234"), b"56789"), 0xBB3D);
+    }
+
+    #[test]
+    fn fit_header() {
+        let gps = Gps::new(vec![point(0, 0.), point(10, 100.)]);
+        let fit = gps.to_fit(Sport::Running);
+
+        assert_eq!(fit[0], 14);
+        assert_eq!(&fit[8..12], b".FIT");
+        let data_size = u32::from_le_bytes(fit[4..8].try_into().unwrap()) as usize;
+        assert_eq!(data_size, fit.len() - 14 - 2);
+        assert_eq!(u16::from_le_bytes([fit[12], fit[13]]), fit_crc(0, &fit[..12]));
+        // CRC over data including the file CRC is zero
+        assert_eq!(fit_crc(0, &fit), 0);
+    }
+
+    #[test]
+    fn fit_empty() {
+        let fit = Gps::default().to_fit(Sport::Generic);
+        assert_eq!(fit.len(), 16);
+        assert_eq!(u32::from_le_bytes(fit[4..8].try_into().unwrap()), 0);
+    }
+
+    #[test]
+    fn lap_distance() {
+        let gps = [
+            Gps::new(vec![point(0, 0.), point(10, 100.)]),
+            // 1 km gap between clips
+            Gps::new(vec![point(100, 1_100.), point(110, 1_200.)]),
+        ];
+        let laps = laps(&gps);
+
+        assert_eq!(laps.len(), 2);
+        assert!((laps[0].distance() - 100.).abs() < 1.);
+        assert!((laps[1].distance() - 100.).abs() < 1.);
+        // Cumulative distance for records
+        assert!((laps[1].end().unwrap().distance - 1_200.).abs() < 10.);
+    }
+
+    #[test]
+    fn lap_skips_unlocked() {
+        let mut unlocked = point(5, 50.);
+        unlocked.fix = 0;
+        let gps = [Gps::new(vec![point(0, 0.), unlocked, GoProPoint::default().with_fix(3), point(10, 100.)])];
+        let laps = laps(&gps);
+
+        assert_eq!(laps[0].points.len(), 2);
+    }
+}
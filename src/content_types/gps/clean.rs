@@ -0,0 +1,418 @@
+//! Cleaning of GPS logs: outlier rejection and smoothing.
+//!
+//! GoPro cameras may log cached (repeated) or jumpy positions, even for
+//! points with a satellite lock, which `Gps::prune()` does not catch
+//! since it only filters on satellite lock and DOP.
+//!
+//! 1. Outlier rejection: points are compared against the last accepted
+//!    point, and rejected if the distance between them implies a speed
+//!    above the absolute limit, or well above the logged (Doppler derived)
+//!    speed, if the position jumps with no time elapsed,
+//!    if the logged speed changes faster than the acceleration limit,
+//!    or if the position is unchanged while the logged speed says the camera
+//!    is moving.
+//! 2. Smoothing (optional): a constant-velocity Kalman filter with
+//!    a Rauch–Tung–Striebel (RTS) backward pass, where measurement noise
+//!    is derived from DOP, so that points with poor precision
+//!    are adjusted more.
+//!
+//! ```rs
+//! use gpmf_rs::{CleanOptions, Gpmf};
+//! use std::path::Path;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! let (gps, report) = gpmf.gps()
+//!     .prune(Some(2), None)
+//!     .clean(&CleanOptions::default());
+//! println!("Rejected {} points", report.rejected.len());
+//! ```
+
+use time::Duration;
+
+use super::{Gps, simplify::LocalProjection};
+
+/// Options for `Gps::clean()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CleanOptions {
+    /// Max plausible speed in m/s between two points,
+    /// regardless of logged speed.
+    pub max_speed: f64,
+    /// Max difference in m/s between speed derived from positions
+    /// and logged 3D speed.
+    pub speed_margin: f64,
+    /// Max distance in meters between two points
+    /// with the same timestamp.
+    pub max_jump: f64,
+    /// Max plausible change in logged 2D speed, in m/s².
+    pub max_acceleration: f64,
+    /// Logged 2D speed in m/s above which an unchanged
+    /// position is considered cached.
+    pub stale_speed: f64,
+    /// Number of consecutive rejected points after which
+    /// the current point is accepted, in case the last accepted point
+    /// was an outlier itself.
+    pub max_rejected: usize,
+    /// Apply Kalman/RTS smoothing to accepted points.
+    pub smooth: bool,
+    /// Min distance in meters a point must be moved by smoothing
+    /// to be listed in `CleanReport::smoothed`.
+    pub smooth_threshold: f64,
+    /// Process noise for smoothing as acceleration standard deviation
+    /// in m/s². Higher values follow the measurements more closely.
+    pub process_noise: f64,
+    /// Position standard deviation in meters at DOP 1.0
+    /// (user equivalent range error). Measurement noise
+    /// is this value multiplied by DOP.
+    pub uere: f64,
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        Self {
+            max_speed: 100.,
+            speed_margin: 10.,
+            max_jump: 10.,
+            max_acceleration: 15.,
+            stale_speed: 2.,
+            max_rejected: 10,
+            smooth: true,
+            smooth_threshold: 0.01,
+            process_noise: 1.,
+            uere: 5.,
+        }
+    }
+}
+
+/// Reason for rejecting a point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rejection {
+    /// Speed derived from positions exceeds max speed,
+    /// or logged speed by more than the margin,
+    /// or position jumps with no time elapsed.
+    Speed,
+    /// Change in logged speed exceeds max acceleration.
+    Acceleration,
+    /// Position unchanged while logged speed indicates movement.
+    Stale,
+}
+
+/// Points modified by `Gps::clean()`.
+/// Indices refer to the points in the original `Gps`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CleanReport {
+    /// Rejected (removed) points.
+    pub rejected: Vec<(usize, Rejection)>,
+    /// Smoothed points, with the 3D distance in meters
+    /// the point was moved. Only points moved more than
+    /// `CleanOptions::smooth_threshold` are listed,
+    /// since smoothing adjusts nearly every point slightly.
+    pub smoothed: Vec<(usize, f64)>,
+}
+
+/// Constant-velocity Kalman filter state for a single axis,
+/// position and velocity.
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    x: [f64; 2],
+    p: [[f64; 2]; 2],
+}
+
+impl Axis {
+    fn new(position: f64, variance: f64) -> Self {
+        Self {
+            x: [position, 0.],
+            // Velocity unknown at start, large variance
+            // to avoid biasing the start of the track towards standstill
+            p: [[variance, 0.], [0., 1e6]],
+        }
+    }
+
+    /// Predicts state `dt` seconds ahead, with process noise `q`
+    /// (acceleration variance).
+    fn predict(&self, dt: f64, q: f64) -> Self {
+        let p = self.p;
+        Self {
+            x: [self.x[0] + dt * self.x[1], self.x[1]],
+            p: [
+                [
+                    p[0][0] + dt * (p[1][0] + p[0][1]) + dt * dt * p[1][1] + q * dt.powi(4) / 4.,
+                    p[0][1] + dt * p[1][1] + q * dt.powi(3) / 2.,
+                ],
+                [
+                    p[1][0] + dt * p[1][1] + q * dt.powi(3) / 2.,
+                    p[1][1] + q * dt * dt,
+                ],
+            ],
+        }
+    }
+
+    /// Updates state with position measurement with variance `r`.
+    fn update(&self, z: f64, r: f64) -> Self {
+        let p = self.p;
+        let s = p[0][0] + r;
+        let k = [p[0][0] / s, p[1][0] / s];
+        let y = z - self.x[0];
+        Self {
+            x: [self.x[0] + k[0] * y, self.x[1] + k[1] * y],
+            p: [
+                [(1. - k[0]) * p[0][0], (1. - k[0]) * p[0][1]],
+                [p[1][0] - k[1] * p[0][0], p[1][1] - k[1] * p[0][1]],
+            ],
+        }
+    }
+}
+
+/// Kalman filter with RTS smoother for a single axis.
+/// Returns smoothed positions.
+fn smooth_axis(z: &[f64], r: &[f64], dt: &[f64], q: f64) -> Vec<f64> {
+    let Some((z0, r0)) = z.first().zip(r.first()) else {
+        return Vec::new()
+    };
+
+    // Forward pass, filtered and predicted states
+    let mut filtered = vec![Axis::new(*z0, *r0)];
+    let mut predicted = vec![filtered[0]];
+    for i in 1..z.len() {
+        let prediction = filtered[i - 1].predict(dt[i], q);
+        predicted.push(prediction);
+        filtered.push(prediction.update(z[i], r[i]));
+    }
+
+    // Backward pass
+    let mut smoothed = filtered.to_owned();
+    for i in (0..z.len().saturating_sub(1)).rev() {
+        let (f, pr) = (&filtered[i], &predicted[i + 1]);
+        // C = P_f * F^T * P_p^-1
+        let pf_ft = [
+            [f.p[0][0] + dt[i + 1] * f.p[0][1], f.p[0][1]],
+            [f.p[1][0] + dt[i + 1] * f.p[1][1], f.p[1][1]],
+        ];
+        let det = pr.p[0][0] * pr.p[1][1] - pr.p[0][1] * pr.p[1][0];
+        if det.abs() < f64::EPSILON {
+            continue
+        }
+        let inv = [
+            [pr.p[1][1] / det, -pr.p[0][1] / det],
+            [-pr.p[1][0] / det, pr.p[0][0] / det],
+        ];
+        let c = [
+            [
+                pf_ft[0][0] * inv[0][0] + pf_ft[0][1] * inv[1][0],
+                pf_ft[0][0] * inv[0][1] + pf_ft[0][1] * inv[1][1],
+            ],
+            [
+                pf_ft[1][0] * inv[0][0] + pf_ft[1][1] * inv[1][0],
+                pf_ft[1][0] * inv[0][1] + pf_ft[1][1] * inv[1][1],
+            ],
+        ];
+        let dx = [
+            smoothed[i + 1].x[0] - pr.x[0],
+            smoothed[i + 1].x[1] - pr.x[1],
+        ];
+        smoothed[i].x = [
+            f.x[0] + c[0][0] * dx[0] + c[0][1] * dx[1],
+            f.x[1] + c[1][0] * dx[0] + c[1][1] * dx[1],
+        ];
+    }
+
+    smoothed.iter().map(|s| s.x[0]).collect()
+}
+
+impl Gps {
+    /// Returns cleaned GPS log, with outliers removed and
+    /// (optionally) positions smoothed, together with a report
+    /// of which points were rejected or modified.
+    /// Points are assumed to be ordered by timestamp.
+    pub fn clean(&self, options: &CleanOptions) -> (Self, CleanReport) {
+        let mut report = CleanReport::default();
+
+        // Outlier rejection
+        let mut accepted: Vec<usize> = Vec::new();
+        let mut rejected_in_row = 0;
+        for (i, point) in self.iter().enumerate() {
+            let Some(last) = accepted.last().map(|j| &self.points()[*j]) else {
+                accepted.push(i);
+                continue
+            };
+
+            let dt = (point.timestamp - last.timestamp).as_seconds_f64();
+            let distance = last.haversine(point);
+            let rejection = match dt > 0. {
+                true => {
+                    let speed = distance / dt;
+                    let logged = point.speed3d.max(last.speed3d).max(point.speed2d).max(last.speed2d);
+                    if speed > options.max_speed || speed > logged + options.speed_margin {
+                        Some(Rejection::Speed)
+                    } else if (point.speed2d - last.speed2d).abs() / dt > options.max_acceleration {
+                        Some(Rejection::Acceleration)
+                    } else {
+                        None
+                    }
+                },
+                // Same timestamp, only a changed position is suspicious
+                false => (distance > options.max_jump).then_some(Rejection::Speed),
+            };
+            let rejection = rejection.or_else(|| (
+                point.latitude() == last.latitude()
+                && point.longitude() == last.longitude()
+                && point.speed2d >= options.stale_speed
+            ).then_some(Rejection::Stale));
+
+            match rejection {
+                Some(reason) if rejected_in_row < options.max_rejected => {
+                    report.rejected.push((i, reason));
+                    rejected_in_row += 1;
+                },
+                _ => {
+                    accepted.push(i);
+                    rejected_in_row = 0;
+                },
+            }
+        }
+
+        let mut points: Vec<_> = accepted.iter()
+            .map(|i| self.points()[*i].to_owned())
+            .collect();
+
+        // Kalman/RTS smoothing
+        if options.smooth && let Some(projection) = LocalProjection::new(&points) {
+            let xy: Vec<(f64, f64)> = points.iter().map(|p| projection.to_xy(p)).collect();
+            let dt: Vec<f64> = std::iter::once(0.)
+                .chain(points.windows(2)
+                    .map(|w| (w[1].timestamp - w[0].timestamp).max(Duration::ZERO).as_seconds_f64()))
+                .collect();
+            // Measurement variance from DOP. Vertical precision
+            // is typically worse than horizontal.
+            let r: Vec<f64> = points.iter()
+                .map(|p| (options.uere * p.dop.max(0.5)).powi(2))
+                .collect();
+            let r_alt: Vec<f64> = r.iter().map(|r| r * 1.5_f64.powi(2)).collect();
+            let q = options.process_noise.powi(2);
+
+            let x = smooth_axis(&xy.iter().map(|p| p.0).collect::<Vec<_>>(), &r, &dt, q);
+            let y = smooth_axis(&xy.iter().map(|p| p.1).collect::<Vec<_>>(), &r, &dt, q);
+            let alt = smooth_axis(&points.iter().map(|p| p.altitude()).collect::<Vec<_>>(), &r_alt, &dt, q);
+
+            for (k, point) in points.iter_mut().enumerate() {
+                let (lat, lon) = projection.to_lat_lon(x[k], y[k]);
+                let moved = (x[k] - xy[k].0)
+                    .hypot(y[k] - xy[k].1)
+                    .hypot(alt[k] - point.altitude());
+                if moved > options.smooth_threshold {
+                    report.smoothed.push((accepted[k], moved));
+                }
+                *point = point.to_owned()
+                    .with_lat_lon(lat, lon)
+                    .with_altitude(alt[k]);
+            }
+        }
+
+        (Self::new(points), report)
+    }
+
+    /// Mutably clean GPS log. Returns report of
+    /// rejected and modified points. See `Gps::clean()`.
+    pub fn clean_mut(&mut self, options: &CleanOptions) -> CleanReport {
+        let (gps, report) = self.clean(options);
+        *self = gps;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::{CleanOptions, Rejection};
+    use crate::{GoProPoint, Gps};
+
+    /// Degrees latitude per meter.
+    const DEG_PER_M: f64 = 1. / 111_195.;
+
+    fn point(seconds: f64, north: f64, east: f64, speed: f64) -> GoProPoint {
+        GoProPoint {
+            speed2d: speed,
+            speed3d: speed,
+            dop: 1.,
+            fix: 3,
+            timestamp: Duration::seconds_f64(seconds),
+            ..GoProPoint::default()
+        }.with_lat_lon(north * DEG_PER_M, east * DEG_PER_M)
+    }
+
+    /// Points one second apart, moving north at `speed` m/s.
+    fn track(speed: f64) -> Vec<GoProPoint> {
+        (0..10)
+            .map(|i| point(i as f64, i as f64 * speed, 0., speed))
+            .collect()
+    }
+
+    fn options(smooth: bool) -> CleanOptions {
+        CleanOptions {
+            smooth,
+            ..CleanOptions::default()
+        }
+    }
+
+    #[test]
+    fn clean_spike() {
+        let mut points = track(1.);
+        points[5] = point(5., 1_000., 0., 1.);
+
+        let (gps, report) = Gps::new(points).clean(&options(false));
+
+        assert_eq!(report.rejected, vec![(5, Rejection::Speed)]);
+        assert_eq!(gps.len(), 9);
+    }
+
+    #[test]
+    fn clean_jump() {
+        let mut points = track(1.);
+        // Same timestamp as previous point
+        points[5] = point(4., 50., 0., 1.);
+
+        let (_, report) = Gps::new(points).clean(&options(false));
+
+        assert_eq!(report.rejected, vec![(5, Rejection::Speed)]);
+    }
+
+    #[test]
+    fn clean_stale() {
+        let mut points = track(3.);
+        // Cached position while moving
+        points[5] = point(5., 12., 0., 3.);
+
+        let (gps, report) = Gps::new(points).clean(&options(false));
+
+        assert_eq!(report.rejected, vec![(5, Rejection::Stale)]);
+        assert_eq!(gps.len(), 9);
+    }
+
+    #[test]
+    fn clean_smooth() {
+        let mut points = track(1.);
+        // Noisy, but plausible position
+        points[5] = point(5., 5., 5., 1.);
+
+        let (gps, report) = Gps::new(points).clean(&options(true));
+
+        assert!(report.rejected.is_empty());
+        assert!(report.smoothed.iter().any(|(i, moved)| *i == 5 && *moved > 1.));
+        assert!(gps.points()[5].longitude() < 5. * DEG_PER_M);
+    }
+
+    #[test]
+    fn clean_smooth_straight() {
+        let points = track(10.);
+
+        let (gps, report) = Gps::new(points.to_owned()).clean(&options(true));
+
+        assert!(report.rejected.is_empty());
+        assert!(report.smoothed.is_empty());
+        for (a, b) in points.iter().zip(gps.iter()) {
+            assert!(a.haversine(b) < 0.01);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! GeoJSON export for GPS logs.
+
+use std::path::Path;
+
+use ::geojson::{Feature, FeatureCollection, GeoJson, Geometry, JsonObject, JsonValue, Value};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{GpmfError, files::write_new};
+use super::{GoProPoint, Gps};
+
+/// GeoJSON layout for `Gps::to_geojson()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoJsonKind {
+    /// Single `Feature` with a `LineString` geometry.
+    /// Point properties are stored as arrays
+    /// (one value per coordinate) in the feature properties.
+    LineString,
+    /// `FeatureCollection` with one `Point` feature per point.
+    Points,
+}
+
+/// Property names and values for a point.
+fn properties(point: &GoProPoint) -> Vec<(&'static str, JsonValue)> {
+    vec![
+        ("time", point.datetime().ok()
+            .and_then(|dt| dt.format(&Rfc3339).ok())
+            .map(JsonValue::from)
+            .unwrap_or(JsonValue::Null)),
+        ("speed2d", JsonValue::from(point.speed2d)),
+        ("speed3d", JsonValue::from(point.speed3d)),
+        ("dop", JsonValue::from(point.dop)),
+        ("fix", JsonValue::from(point.fix)),
+        // Relative to video start
+        ("timestamp", JsonValue::from(point.timestamp.as_seconds_f64())),
+    ]
+}
+
+/// GeoJSON position `[longitude, latitude, altitude]`.
+fn position(point: &GoProPoint) -> Vec<f64> {
+    vec![point.longitude(), point.latitude(), point.altitude()]
+}
+
+impl Gps {
+    /// Returns GPS log as GeoJSON, either as a single `LineString` feature,
+    /// or as a collection of `Point` features.
+    /// Properties are datetime (`time`, RFC3339), `speed2d`, `speed3d`, `dop`, `fix`,
+    /// and `timestamp` (seconds relative to video start).
+    ///
+    /// Note that this exports all points, bad and good.
+    /// Use `Gps::prune()` first to exclude points without
+    /// satellite lock.
+    pub fn to_geojson(&self, kind: GeoJsonKind) -> GeoJson {
+        match kind {
+            GeoJsonKind::LineString => GeoJson::Feature(self.to_geojson_linestring()),
+            GeoJsonKind::Points => GeoJson::FeatureCollection(self.to_geojson_points()),
+        }
+    }
+
+    /// Returns GPS log as a single GeoJSON `LineString` feature.
+    /// Point properties are stored as arrays, with one value per coordinate.
+    pub fn to_geojson_linestring(&self) -> Feature {
+        let mut props = JsonObject::new();
+        for point in self.iter() {
+            for (key, value) in properties(point) {
+                if let JsonValue::Array(values) = props.entry(key)
+                    .or_insert_with(|| JsonValue::Array(Vec::new()))
+                {
+                    values.push(value);
+                }
+            }
+        }
+
+        Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(Value::LineString(
+                self.iter().map(position).collect()
+            ))),
+            id: None,
+            properties: Some(props),
+            foreign_members: None,
+        }
+    }
+
+    /// Returns GPS log as a GeoJSON `FeatureCollection`,
+    /// with one `Point` feature per point.
+    pub fn to_geojson_points(&self) -> FeatureCollection {
+        let features = self.iter()
+            .map(|point| Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(Value::Point(position(point)))),
+                id: None,
+                properties: Some(properties(point).into_iter()
+                    .map(|(k, v)| (k.to_owned(), v))
+                    .collect()),
+                foreign_members: None,
+            })
+            .collect();
+
+        FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        }
+    }
+
+    /// Write GeoJSON file.
+    pub fn write_geojson(&self, path: &Path, kind: GeoJsonKind) -> Result<(), GpmfError> {
+        write_new(path, self.to_geojson(kind).to_string().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::geojson::{GeoJson, JsonObject, JsonValue, Value};
+    use time::Duration;
+
+    use crate::{GoProPoint, Gps};
+    use super::GeoJsonKind;
+
+    fn gps() -> Gps {
+        let point = |lat: f64, lon: f64, alt: f64, seconds: i64| {
+            let mut p = GoProPoint {
+                speed2d: 1.5,
+                speed3d: 1.75,
+                timestamp: Duration::seconds(seconds),
+                ..GoProPoint::default()
+            }
+                .with_lat_lon(lat, lon)
+                .with_altitude(alt)
+                .with_dop(1.25)
+                .with_fix(3);
+            p.add_duration(Duration::seconds(seconds));
+            p
+        };
+        Gps::new(vec![point(60.0, 18.0, 25.0, 1), point(60.5, 18.5, 26.5, 2)])
+    }
+
+    fn keys(props: &JsonObject) -> Vec<&str> {
+        let mut keys: Vec<&str> = props.keys().map(|k| k.as_str()).collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn geojson_points() {
+        let collection = gps().to_geojson_points();
+        assert_eq!(collection.features.len(), 2);
+
+        let feature = &collection.features[1];
+        // Longitude, latitude, altitude
+        assert_eq!(
+            feature.geometry.as_ref().map(|g| &g.value),
+            Some(&Value::Point(vec![18.5, 60.5, 26.5]))
+        );
+
+        let props = feature.properties.as_ref().unwrap();
+        assert_eq!(keys(props), vec!["dop", "fix", "speed2d", "speed3d", "time", "timestamp"]);
+        assert_eq!(props["time"], JsonValue::from("2000-01-01T00:00:02Z"));
+        assert_eq!(props["speed2d"], JsonValue::from(1.5));
+        assert_eq!(props["speed3d"], JsonValue::from(1.75));
+        assert_eq!(props["dop"], JsonValue::from(1.25));
+        assert_eq!(props["fix"], JsonValue::from(3));
+        assert_eq!(props["timestamp"], JsonValue::from(2.0));
+    }
+
+    #[test]
+    fn geojson_linestring() {
+        let feature = gps().to_geojson_linestring();
+
+        assert_eq!(
+            feature.geometry.as_ref().map(|g| &g.value),
+            Some(&Value::LineString(vec![vec![18.0, 60.0, 25.0], vec![18.5, 60.5, 26.5]]))
+        );
+
+        // One value per coordinate
+        let props = feature.properties.as_ref().unwrap();
+        assert_eq!(keys(props), vec!["dop", "fix", "speed2d", "speed3d", "time", "timestamp"]);
+        assert_eq!(props["time"], JsonValue::from(vec!["2000-01-01T00:00:01Z", "2000-01-01T00:00:02Z"]));
+        assert_eq!(props["timestamp"], JsonValue::from(vec![1.0, 2.0]));
+    }
+
+    #[test]
+    fn geojson_empty() {
+        let geojson = Gps::default().to_geojson(GeoJsonKind::Points).to_string();
+        match geojson.parse::<GeoJson>() {
+            Ok(GeoJson::FeatureCollection(collection)) => assert!(collection.features.is_empty()),
+            other => panic!("expected empty FeatureCollection, got {other:?}"),
+        }
+    }
+}
@@ -12,6 +12,7 @@ use super::GoProPoint;
 
 /// Gps point cluster, converted from `GPS5` or `GPS9`.
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Gps(Vec<GoProPoint>);
 
 #[cfg(feature = "gpx")]
@@ -71,9 +72,22 @@ impl Gps {
     //     self.0.last().and_then(|p| p.time.as_ref())
     // }
 
+    /// Returns points with a timestamp within `start` (inclusive)
+    /// to `end` (exclusive), relative to video start.
+    /// Timestamps remain relative to video start.
+    pub fn slice(&self, start: Duration, end: Duration) -> Self {
+        Self(self.0.iter()
+            .filter(|p| p.timestamp >= start && p.timestamp < end)
+            .cloned()
+            .collect())
+    }
+
     /// Returns the start of the GPMF stream as `PrimitiveDateTime`.
     /// Returns `None` if no points were logged or if no points with minimum
     /// level of satellite lock were logged. Defaults to 2D lock if `min_gps_fix` is `None`.
+    ///
+    /// Derived from a single point. See `TimeModel` for a start time
+    /// fitted to all points, that also corrects for camera clock drift.
     // pub fn t0(&self, min_gps_fix: Option<u32>) -> Option<PrimitiveDateTime> {
     pub fn t0(&self, min_gps_fix: Option<u32>) -> Option<OffsetDateTime> {
         let first_point = self
@@ -176,8 +190,10 @@ impl Gps {
     }
 
     /// Returns tuples representing 2D
-    /// bounding box.
-    fn bounds(&self) -> Option<[(f64, f64); 4]> {
+    /// bounding box as `(latitude, longitude)`,
+    /// in the order south-west, south-east,
+    /// north-west, north-east.
+    pub fn bounds(&self) -> Option<[(f64, f64); 4]> {
         if !self.is_empty() {
             let mut lat_min = self.first().map(|p| p.latitude())?;
             let mut lat_max = self.first().map(|p| p.latitude())?;
@@ -384,3 +400,29 @@ pub(crate) fn average_points(points: &[GoProPoint]) -> Option<GoProPoint> {
 fn average(nums: &[f64]) -> f64 {
     nums.iter().sum::<f64>() / nums.len() as f64
 }
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use crate::{GoProPoint, Gps};
+
+    #[test]
+    fn gps_slice() {
+        let gps = Gps::new((0..4)
+            .map(|i| GoProPoint {
+                timestamp: Duration::seconds(i),
+                ..GoProPoint::default()
+            })
+            .collect());
+        let timestamps = |gps: Gps| -> Vec<i64> {
+            gps.iter().map(|p| p.timestamp.whole_seconds()).collect()
+        };
+
+        // Start inclusive, end exclusive
+        assert_eq!(timestamps(gps.slice(Duration::seconds(1), Duration::seconds(3))), vec![1, 2]);
+        assert_eq!(timestamps(gps.slice(Duration::ZERO, Duration::seconds(10))), vec![0, 1, 2, 3]);
+        assert!(gps.slice(Duration::seconds(2), Duration::seconds(2)).is_empty());
+        assert!(gps.slice(Duration::seconds(4), Duration::seconds(10)).is_empty());
+    }
+}
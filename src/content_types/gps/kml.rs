@@ -0,0 +1,284 @@
+//! KML/KMZ export for GPS logs, e.g. for review in Google Earth.
+//!
+//! Points are exported as `gx:Track` elements, with one `when` element
+//! per point, so that the time slider in Google Earth can be used to
+//! play back the track. The track is split into segments with the same
+//! satellite lock, each styled according to fix quality.
+
+use std::path::Path;
+#[cfg(feature = "kmz")]
+use std::{fs::File, io::Write};
+
+use time::format_description::well_known::Rfc3339;
+
+use crate::{GpmfError, files::write_new};
+use super::{GoProPoint, Gps};
+
+/// Vertical datum for altitude values.
+///
+/// GoPro cameras logged altitude above the WGS84 ellipsoid,
+/// until `GPSA` (GPS altitude) was added in firmware updates
+/// for Hero8/Hero9, which then logs `MSLV` (mean sea level).
+/// See `Gpmf::altitude_reference()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AltitudeReference {
+    /// Altitude above mean sea level (`GPSA` is `MSLV`).
+    MeanSeaLevel,
+    /// Altitude above the WGS84 ellipsoid (no `GPSA`).
+    #[default]
+    Ellipsoid,
+}
+
+impl AltitudeReference {
+    /// KML `altitudeMode`.
+    ///
+    /// KML `absolute` altitude is relative to mean sea level.
+    /// Ellipsoid heights differ from mean sea level by up to
+    /// roughly 100 meters depending on location, so these are
+    /// clamped to ground to avoid tracks floating above,
+    /// or being buried below the terrain.
+    pub fn kml_altitude_mode(&self) -> &'static str {
+        match self {
+            AltitudeReference::MeanSeaLevel => "absolute",
+            AltitudeReference::Ellipsoid => "clampToGround",
+        }
+    }
+}
+
+/// Style ID and KML color (`aabbggrr`) for satellite lock.
+fn fix_style(fix: u32) -> (&'static str, &'static str) {
+    match fix {
+        3.. => ("fix3d", "ff00ff00"), // green
+        2 => ("fix2d", "ff00ffff"), // yellow
+        _ => ("nofix", "ff0000ff"), // red
+    }
+}
+
+/// Escapes XML special characters.
+pub(crate) fn kml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Returns complete KML document with styles and schema
+/// for per-point track data, with `body` as document content.
+pub(crate) fn kml_document(name: &str, body: &str) -> String {
+    let styles = [0, 2, 3].iter()
+        .map(|fix| {
+            let (id, color) = fix_style(*fix);
+            format!(concat!(
+                "<Style id=\"{id}\">",
+                "<IconStyle><color>{color}</color><scale>0.6</scale></IconStyle>",
+                "<LineStyle><color>{color}</color><width>4</width></LineStyle>",
+                "</Style>\n"),
+                id = id, color = color
+            )
+        })
+        .collect::<String>();
+
+    let schema = [
+        ("speed2d", "2D speed (m/s)"),
+        ("speed3d", "3D speed (m/s)"),
+        ("dop", "DOP"),
+        ("fix", "GPS fix"),
+        ("timestamp", "Video time (s)"),
+    ].iter()
+        .map(|(field, display)| format!(
+            "<gx:SimpleArrayField name=\"{field}\" type=\"float\"><displayName>{display}</displayName></gx:SimpleArrayField>\n"
+        ))
+        .collect::<String>();
+
+    format!(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n",
+        "<Document>\n",
+        "<name>{name}</name>\n",
+        "{styles}",
+        "<Schema id=\"gopro\">\n{schema}</Schema>\n",
+        "{body}",
+        "</Document>\n",
+        "</kml>\n"),
+        name = kml_escape(name), styles = styles, schema = schema, body = body
+    )
+}
+
+/// Returns `Placemark` with a `Point` geometry.
+pub(crate) fn kml_placemark(
+    name: &str,
+    description: &str,
+    point: &GoProPoint,
+    altitude: AltitudeReference
+) -> String {
+    let when = point.datetime().ok()
+        .and_then(|dt| dt.format(&Rfc3339).ok())
+        .map(|dt| format!("<TimeStamp><when>{dt}</when></TimeStamp>"))
+        .unwrap_or_default();
+    format!(concat!(
+        "<Placemark>\n",
+        "<name>{name}</name>\n",
+        "<description>{description}</description>\n",
+        "{when}\n",
+        "<styleUrl>#{style}</styleUrl>\n",
+        "<Point><altitudeMode>{mode}</altitudeMode><coordinates>{lon},{lat},{alt}</coordinates></Point>\n",
+        "</Placemark>\n"),
+        name = kml_escape(name),
+        description = kml_escape(description),
+        when = when,
+        style = fix_style(point.fix).0,
+        mode = altitude.kml_altitude_mode(),
+        lon = point.longitude(),
+        lat = point.latitude(),
+        alt = point.altitude(),
+    )
+}
+
+/// Returns `gx:Track` placemark for a run of points
+/// with the same style. Points without a valid datetime
+/// are skipped, since each `gx:coord` requires a `when`.
+fn kml_track(points: &[GoProPoint], altitude: AltitudeReference) -> String {
+    let points: Vec<(&GoProPoint, String)> = points.iter()
+        .filter_map(|p| p.datetime().ok()
+            .and_then(|dt| dt.format(&Rfc3339).ok())
+            .map(|dt| (p, dt)))
+        .collect();
+    let Some((first, _)) = points.first() else {
+        return String::new()
+    };
+    let (style, _) = fix_style(first.fix);
+
+    let when = points.iter()
+        .map(|(_, dt)| format!("<when>{dt}</when>\n"))
+        .collect::<String>();
+    let coords = points.iter()
+        .map(|(p, _)| format!("<gx:coord>{} {} {}</gx:coord>\n", p.longitude(), p.latitude(), p.altitude()))
+        .collect::<String>();
+    let data = |name: &str, value: fn(&GoProPoint) -> f64| -> String {
+        format!(
+            "<gx:SimpleArrayData name=\"{name}\">\n{}</gx:SimpleArrayData>\n",
+            points.iter()
+                .map(|(p, _)| format!("<gx:value>{}</gx:value>\n", value(p)))
+                .collect::<String>()
+        )
+    };
+
+    format!(concat!(
+        "<Placemark>\n",
+        "<name>{name}</name>\n",
+        "<styleUrl>#{style}</styleUrl>\n",
+        "<gx:Track>\n",
+        "<altitudeMode>{mode}</altitudeMode>\n",
+        "{when}",
+        "{coords}",
+        "<ExtendedData><SchemaData schemaUrl=\"#gopro\">\n",
+        "{speed2d}{speed3d}{dop}{fix}{timestamp}",
+        "</SchemaData></ExtendedData>\n",
+        "</gx:Track>\n",
+        "</Placemark>\n"),
+        name = style,
+        style = style,
+        mode = altitude.kml_altitude_mode(),
+        when = when,
+        coords = coords,
+        speed2d = data("speed2d", |p| p.speed2d),
+        speed3d = data("speed3d", |p| p.speed3d),
+        dop = data("dop", |p| p.dop),
+        fix = data("fix", |p| p.fix as f64),
+        timestamp = data("timestamp", |p| p.timestamp.as_seconds_f64()),
+    )
+}
+
+impl Gps {
+    /// Returns `gx:Track` placemarks, one for each
+    /// run of points with the same fix quality.
+    pub(crate) fn to_kml_tracks(&self, altitude: AltitudeReference) -> String {
+        self.points()
+            .chunk_by(|a, b| fix_style(a.fix).0 == fix_style(b.fix).0)
+            .map(|run| kml_track(run, altitude))
+            .collect()
+    }
+
+    /// Returns GPS log as a KML document, with `gx:Track` elements
+    /// that include a datetime (`when`) for each point.
+    /// The track is split into segments styled by satellite lock:
+    /// green for 3D lock, yellow for 2D lock, red for no lock.
+    ///
+    /// Use `Gpmf::altitude_reference()` to determine `altitude`.
+    pub fn to_kml(&self, name: &str, altitude: AltitudeReference) -> String {
+        kml_document(name, &format!(
+            "<Folder>\n<name>{}</name>\n{}</Folder>\n",
+            kml_escape(name),
+            self.to_kml_tracks(altitude)
+        ))
+    }
+
+    /// Write KML file.
+    pub fn write_kml(&self, path: &Path, altitude: AltitudeReference) -> Result<(), GpmfError> {
+        let name = path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        write_new(path, self.to_kml(&name, altitude).as_bytes())
+    }
+
+    /// Write KMZ file (zipped KML).
+    #[cfg(feature = "kmz")]
+    pub fn write_kmz(&self, path: &Path, altitude: AltitudeReference) -> Result<(), GpmfError> {
+        let name = path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        write_kmz(path, &self.to_kml(&name, altitude))
+    }
+}
+
+/// Writes KML document to disk as KMZ,
+/// i.e. a zip archive containing `doc.kml`.
+#[cfg(feature = "kmz")]
+pub(crate) fn write_kmz(path: &Path, kml: &str) -> Result<(), GpmfError> {
+    let file = File::create_new(path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    zip.start_file("doc.kml", zip::write::SimpleFileOptions::default())?;
+    zip.write_all(kml.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use crate::{AltitudeReference, GoProPoint, Gps};
+
+    #[test]
+    fn kml_track_skips_undated() {
+        let point = |lat: f64, seconds: i64| {
+            let mut p = GoProPoint::default()
+                .with_lat_lon(lat, 18.0)
+                .with_fix(3);
+            p.add_duration(Duration::seconds(seconds));
+            p
+        };
+        // Before 1970, no valid datetime
+        let undated = point(60.5, -40 * 365 * 86_400);
+        let gps = Gps::new(vec![point(60.0, 0), undated, point(61.0, 1)]);
+
+        let kml = gps.to_kml("test", AltitudeReference::Ellipsoid);
+        assert_eq!(kml.matches("<gx:Track>").count(), 1);
+        assert_eq!(kml.matches("<when>").count(), 2);
+        assert_eq!(kml.matches("<gx:coord>").count(), 2);
+        assert!(!kml.contains("<when></when>"));
+        assert!(!kml.contains("60.5"));
+        assert!(kml.contains("<when>2000-01-01T00:00:01Z</when>"));
+        assert!(kml.contains("<gx:coord>18 61 0</gx:coord>"));
+    }
+
+    #[test]
+    fn kml_track_all_undated() {
+        let mut p = GoProPoint::default().with_fix(3);
+        p.add_duration(Duration::days(-40 * 365));
+        let kml = Gps::new(vec![p]).to_kml("test", AltitudeReference::Ellipsoid);
+        assert!(!kml.contains("<Placemark>"));
+    }
+}
@@ -1,11 +1,28 @@
 //! Structs and methods for processing GPS data (`GPS5` and `GPS9`).
 
 // mod gps_old;
+mod activity;
+mod clean;
+mod geojson;
 mod gps;
+mod kml;
+mod nmea;
 // mod point_old;
 mod point;
+mod simplify;
+mod stats;
 
 // pub use gps_old::Gps;
 // pub use point_old::GoProPoint;
+pub use activity::Sport;
+pub(crate) use activity::{fit_activity, tcx_activity};
+pub use clean::{CleanOptions, CleanReport, Rejection};
+pub use geojson::GeoJsonKind;
 pub use gps::Gps;
+pub use kml::AltitudeReference;
+pub(crate) use kml::{kml_document, kml_escape, kml_placemark};
+#[cfg(feature = "kmz")]
+pub(crate) use kml::write_kmz;
 pub use point::GoProPoint;
+pub use simplify::{Simplification, SimplifyOptions};
+pub use stats::{GpsStats, StatsOptions};
@@ -0,0 +1,234 @@
+//! NMEA 0183 output for GPS logs, for tools that only accept NMEA,
+//! either as a file, or replayed in real time, e.g. over TCP.
+//!
+//! Each point generates three sentences:
+//! - `$GPRMC`: time, date, position, speed and course.
+//! - `$GPGGA`: time, position, fix quality, HDOP and altitude.
+//! - `$GPGSA`: fix mode and HDOP.
+//!
+//! Satellite count and satellite IDs are not logged by GoPro,
+//! and are left empty. GoPro DOP is used as HDOP.
+//!
+//! ```rs
+//! use gpmf_rs::Gpmf;
+//! use std::{net::TcpListener, path::Path};
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! let listener = TcpListener::bind("127.0.0.1:10110")?;
+//! let (mut stream, _) = listener.accept()?;
+//! gpmf.replay_nmea(&mut stream, 1.0)?;
+//! ```
+
+use std::{
+    io::Write,
+    path::Path,
+    time::Instant,
+};
+
+use time::OffsetDateTime;
+
+use crate::{GpmfError, files::write_new};
+use super::{GoProPoint, Gps};
+
+/// Meters per second to knots.
+const MPS_TO_KNOTS: f64 = 1.943_844;
+
+/// Returns complete sentence, with `$`, checksum
+/// (XOR of all bytes between `$` and `*`) and CRLF.
+fn sentence(body: &str) -> String {
+    let checksum = body.bytes().fold(0_u8, |acc, b| acc ^ b);
+    format!("${body}*{checksum:02X}\r\n")
+}
+
+/// Formats coordinate as `(d)ddmm.mmmm` and hemisphere.
+fn coordinate(value: f64, degree_digits: usize, positive: char, negative: char) -> (String, char) {
+    let hemisphere = match value < 0. {
+        true => negative,
+        false => positive,
+    };
+    let abs = value.abs();
+    let mut degrees = abs.trunc() as u32;
+    // Round before formatting to avoid e.g. 59.99999 -> 60.0000
+    let mut minutes = ((abs - abs.trunc()) * 60. * 10_000.).round() / 10_000.;
+    if minutes >= 60. {
+        degrees += 1;
+        minutes -= 60.;
+    }
+    (format!("{degrees:0degree_digits$}{minutes:07.4}"), hemisphere)
+}
+
+/// Formats time as `hhmmss.ss`.
+fn nmea_time(datetime: Option<&OffsetDateTime>) -> String {
+    datetime
+        .map(|dt| format!(
+            "{:02}{:02}{:02}.{:02}",
+            dt.hour(), dt.minute(), dt.second(), dt.millisecond() / 10
+        ))
+        .unwrap_or_default()
+}
+
+/// Formats date as `ddmmyy`.
+fn nmea_date(datetime: Option<&OffsetDateTime>) -> String {
+    datetime
+        .map(|dt| format!(
+            "{:02}{:02}{:02}",
+            dt.day(), u8::from(dt.month()), dt.year().rem_euclid(100)
+        ))
+        .unwrap_or_default()
+}
+
+/// Course over ground in degrees from `from` to `to`.
+fn course(from: &GoProPoint, to: &GoProPoint) -> f64 {
+    let (lat1, lat2) = (from.latitude().to_radians(), to.latitude().to_radians());
+    let dlon = (to.longitude() - from.longitude()).to_radians();
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.)
+}
+
+impl GoProPoint {
+    /// Returns `$GPRMC`, `$GPGGA` and `$GPGSA` sentences for point,
+    /// each terminated by CRLF.
+    /// `previous` is used to derive course over ground,
+    /// which is left empty if not set or if the position is unchanged.
+    pub fn to_nmea(&self, previous: Option<&GoProPoint>) -> [String; 3] {
+        let datetime = self.datetime().ok();
+        let time = nmea_time(datetime.as_ref());
+        let (lat, ns) = coordinate(self.latitude(), 2, 'N', 'S');
+        let (lon, ew) = coordinate(self.longitude(), 3, 'E', 'W');
+        let valid = self.fix >= 2;
+
+        let course = previous
+            .filter(|p| p.latitude() != self.latitude() || p.longitude() != self.longitude())
+            .map(|p| format!("{:.1}", course(p, self)))
+            .unwrap_or_default();
+
+        let rmc = format!(
+            "GPRMC,{time},{status},{lat},{ns},{lon},{ew},{speed:.2},{course},{date},,,{mode}",
+            status = if valid {'A'} else {'V'},
+            speed = self.speed2d * MPS_TO_KNOTS,
+            date = nmea_date(datetime.as_ref()),
+            mode = if valid {'A'} else {'N'},
+        );
+
+        let gga = format!(
+            "GPGGA,{time},{lat},{ns},{lon},{ew},{quality},,{hdop:.2},{alt:.1},M,,M,,",
+            quality = if valid {1} else {0},
+            hdop = self.dop,
+            alt = self.altitude(),
+        );
+
+        // 1 = no fix, 2 = 2D, 3 = 3D
+        let gsa = format!(
+            "GPGSA,A,{mode},,,,,,,,,,,,,,{hdop:.2},",
+            mode = self.fix.clamp(1, 3),
+            hdop = self.dop,
+        );
+
+        [sentence(&rmc), sentence(&gga), sentence(&gsa)]
+    }
+}
+
+impl Gps {
+    /// Returns GPS log as NMEA 0183 sentences,
+    /// `$GPRMC`, `$GPGGA` and `$GPGSA` for each point.
+    /// Fix quality is derived from `GoProPoint::fix`,
+    /// HDOP from `GoProPoint::dop`.
+    ///
+    /// Note that this exports all points, bad and good.
+    /// Use `Gps::prune()` first to exclude points without
+    /// satellite lock.
+    pub fn to_nmea(&self) -> String {
+        self.iter()
+            .enumerate()
+            .flat_map(|(i, p)| p.to_nmea(i.checked_sub(1).and_then(|j| self.points().get(j))))
+            .collect()
+    }
+
+    /// Write NMEA file.
+    pub fn write_nmea(&self, path: &Path) -> Result<(), GpmfError> {
+        write_new(path, self.to_nmea().as_bytes())
+    }
+
+    /// Replays GPS log as NMEA sentences to `writer`,
+    /// e.g. a `TcpStream`, timed according to point timestamps.
+    /// `speed` is a playback speed multiplier,
+    /// `1.0` for real time. A value of `0.0` or below
+    /// writes all sentences without delay.
+    ///
+    /// Blocks until all points are written.
+    pub fn replay_nmea<W: Write>(&self, writer: &mut W, speed: f64) -> Result<(), GpmfError> {
+        let Some(first) = self.first() else {
+            return Ok(())
+        };
+        let start = Instant::now();
+        let mut previous: Option<&GoProPoint> = None;
+
+        for point in self.iter() {
+            if speed > 0. && speed.is_finite() {
+                let target = (point.timestamp - first.timestamp).as_seconds_f64() / speed;
+                let elapsed = start.elapsed().as_secs_f64();
+                if target > elapsed {
+                    std::thread::sleep(std::time::Duration::from_secs_f64(target - elapsed));
+                }
+            }
+            for sentence in point.to_nmea(previous) {
+                writer.write_all(sentence.as_bytes())?;
+            }
+            writer.flush()?;
+            previous = Some(point);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::{coordinate, sentence};
+    use crate::GoProPoint;
+
+    #[test]
+    fn nmea_checksum() {
+        // Reference sentences with known-valid checksums
+        assert_eq!(
+            sentence("GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,"),
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n"
+        );
+        assert_eq!(
+            sentence("GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W"),
+            "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n"
+        );
+    }
+
+    #[test]
+    fn nmea_coordinate() {
+        assert_eq!(coordinate(48.1173, 2, 'N', 'S'), ("4807.0380".to_owned(), 'N'));
+        assert_eq!(coordinate(11.516667, 3, 'E', 'W'), ("01131.0000".to_owned(), 'E'));
+        assert_eq!(coordinate(-33.4489, 2, 'N', 'S'), ("3326.9340".to_owned(), 'S'));
+        assert_eq!(coordinate(-70.6693, 3, 'E', 'W'), ("07040.1580".to_owned(), 'W'));
+        assert_eq!(coordinate(0., 2, 'N', 'S'), ("0000.0000".to_owned(), 'N'));
+        // Minutes rounding to 60
+        assert_eq!(coordinate(12.99999999, 2, 'N', 'S'), ("1300.0000".to_owned(), 'N'));
+        assert_eq!(coordinate(-179.99999999, 3, 'E', 'W'), ("18000.0000".to_owned(), 'W'));
+    }
+
+    #[test]
+    fn nmea_point() {
+        let mut point = GoProPoint {
+            dop: 0.9,
+            fix: 3,
+            ..GoProPoint::default()
+        }
+            .with_lat_lon(48.1173, 11.516667)
+            .with_altitude(545.4);
+        point.add_duration(datetime!(1994-03-23 12:35:19 UTC) - GoProPoint::datetime_default());
+
+        let [rmc, gga, gsa] = point.to_nmea(None);
+        assert_eq!(rmc, "$GPRMC,123519.00,A,4807.0380,N,01131.0000,E,0.00,,230394,,,A*40\r\n");
+        assert_eq!(gga, "$GPGGA,123519.00,4807.0380,N,01131.0000,E,1,,0.90,545.4,M,,M,,*44\r\n");
+        assert_eq!(gsa, "$GPGSA,A,3,,,,,,,,,,,,,,0.90,*0B\r\n");
+    }
+}
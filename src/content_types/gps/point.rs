@@ -2,10 +2,12 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use spatio_types::{geo::Point, point::TemporalPoint3D};
 use time::{Duration, OffsetDateTime, PrimitiveDateTime, ext::NumericalDuration, macros::datetime};
-use crate::{FourCC, Stream, GpmfError, content_types::primitivedatetime_to_string};
+use crate::{FourCC, Stream, GpmfError, TimedSample, content_types::primitivedatetime_to_string};
 
 /// Point derived from GPS data stream.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "GoProPointSerde", into = "GoProPointSerde"))]
 pub struct GoProPoint {
     pub(crate) point: TemporalPoint3D,
     /// 2D speed.
@@ -29,6 +31,59 @@ pub struct GoProPoint {
     pub timestamp: Duration,
 }
 
+/// Flat representation of `GoProPoint` for serialization,
+/// since the underlying point type does not implement serde.
+/// Datetime is stored as RFC3339.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GoProPointSerde {
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    #[serde(with = "time::serde::rfc3339")]
+    datetime: OffsetDateTime,
+    speed2d: f64,
+    speed3d: f64,
+    dop: f64,
+    fix: u32,
+    timestamp: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl From<GoProPoint> for GoProPointSerde {
+    fn from(value: GoProPoint) -> Self {
+        Self {
+            latitude: value.latitude(),
+            longitude: value.longitude(),
+            altitude: value.altitude(),
+            datetime: OffsetDateTime::from(*value.systemtime()),
+            speed2d: value.speed2d,
+            speed3d: value.speed3d,
+            dop: value.dop,
+            fix: value.fix,
+            timestamp: value.timestamp,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<GoProPointSerde> for GoProPoint {
+    fn from(value: GoProPointSerde) -> Self {
+        Self {
+            point: TemporalPoint3D::new(
+                Point::new(value.longitude, value.latitude),
+                value.altitude,
+                SystemTime::from(value.datetime)
+            ),
+            speed2d: value.speed2d,
+            speed3d: value.speed3d,
+            dop: value.dop,
+            fix: value.fix,
+            timestamp: value.timestamp,
+        }
+    }
+}
+
 impl Default for GoProPoint {
     fn default() -> Self {
         Self {
@@ -380,6 +435,80 @@ impl GoProPoint {
         })
     }
 
+    /// For Hero10 and earlier models.
+    /// Returns every point in the `GPS5` cluster
+    /// in the specified DEVC stream, rather than
+    /// a single average as for `GoProPoint::from_gps5()`.
+    ///
+    /// Relative timestamps are spread evenly across
+    /// the `DEVC` duration. Datetime is derived from `GPSU`
+    /// (logged once per cluster) plus the offset from the first
+    /// point in the cluster. `GPSF` (satellite lock) and `GPSP` (DOP)
+    /// are the same for all points in the cluster.
+    pub fn from_gps5_expanded(devc_stream: &Stream) -> Option<Vec<Self>> {
+        Self::from_gps5_timed(devc_stream, None)
+    }
+
+    /// See `GoProPoint::from_gps5_expanded()`.
+    /// If `times` contains one sample per logged `GPS5` value
+    /// in the cluster, e.g. derived from `TSMP` (see `TimedSamples`),
+    /// sample times are used for relative timestamps
+    /// instead of spreading points evenly.
+    pub(crate) fn from_gps5_timed(devc_stream: &Stream, times: Option<&[TimedSample]>) -> Option<Vec<Self>> {
+        let gps5 = devc_stream
+            .find(&FourCC::GPS5)
+            .and_then(|s| s.to_vec_f64())?;
+
+        // REQUIRED, one scale value per GPS5 value
+        let scale = devc_stream
+            .find(&FourCC::SCAL)
+            .and_then(|s| s.to_f64())
+            .filter(|s| s.len() == 5)?;
+
+        let gpsu: SystemTime = devc_stream
+            .find(&FourCC::GPSU)
+                .and_then(|s| s.first_value())
+                .and_then(|v| v.into())?;
+
+        let gpsf: u32 = devc_stream
+            .find(&FourCC::GPSF)
+                .and_then(|s| s.first_value())
+                .and_then(|v| v.into())?;
+
+        let gpsp: u16 = devc_stream
+            .find(&FourCC::GPSP)
+                .and_then(|s| s.first_value())
+                .and_then(|v| v.into())?;
+
+        let (relative, duration) = devc_stream.time.as_ref()
+            .map_or_else(|| (Duration::ZERO, Duration::ZERO), |t| (t.relative, t.duration));
+        let step = duration / gps5.len().max(1) as i32;
+        let times = times.filter(|t| t.len() == gps5.len());
+
+        // Enumerate before filtering so that offsets
+        // match the position in the cluster
+        let points = gps5.iter()
+            .enumerate()
+            .filter(|(_, gps)| gps.len() == 5)
+            .map(|(i, gps)| {
+                let offset = match times {
+                    Some(t) => t[i].time - relative,
+                    None => step * i as i32,
+                };
+                let mut point = Self::from_gps9_slice(gps, &scale);
+                point.point.timestamp = gpsu + offset;
+                Self {
+                    dop: gpsp as f64 / 100.,
+                    fix: gpsf,
+                    timestamp: relative + offset,
+                    ..point
+                }
+            })
+            .collect();
+
+        Some(points)
+    }
+
     /// For Hero11 and later models. These log at 10Hz.
     /// Returns the point cluster in the specified DEVC stream.
     /// GPS9 devices log datetime, GPS fix, GPS dop individually for each point.
@@ -437,3 +566,54 @@ impl GoProPoint {
         primitivedatetime_to_string(&pdt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, macros::datetime};
+
+    use super::GoProPoint;
+    use crate::tests::{gps5_strm, timed};
+
+    #[test]
+    fn gps5_expanded() {
+        let strm = timed(gps5_strm(Some("230101120000.000"), None, vec![
+            vec![600_000_000, 180_000_000, 10_000, 1_000, 2_000],
+            vec![600_000_010, 180_000_020, 10_500, 1_500, 2_500],
+            // Incomplete sample, skipped
+            vec![600_000_020, 180_000_040, 11_000, 2_000],
+            vec![600_000_030, 180_000_060, 11_500, 2_500, 3_500],
+        ]), 10., 1.);
+
+        let points = GoProPoint::from_gps5_expanded(&strm).unwrap();
+
+        assert_eq!(points.len(), 3);
+        // Offsets match the position in the cluster, including the skipped sample
+        assert_eq!(
+            points.iter().map(|p| p.timestamp).collect::<Vec<_>>(),
+            vec![Duration::seconds(10), Duration::seconds_f64(10.25), Duration::seconds_f64(10.75)]
+        );
+        assert_eq!(
+            points.iter().map(|p| p.datetime().unwrap()).collect::<Vec<_>>(),
+            vec![
+                datetime!(2023-01-01 12:00:00 UTC),
+                datetime!(2023-01-01 12:00:00.25 UTC),
+                datetime!(2023-01-01 12:00:00.75 UTC),
+            ]
+        );
+
+        let last = &points[2];
+        assert!((last.latitude() - 60.000_003).abs() < 1e-9);
+        assert!((last.longitude() - 18.000_006).abs() < 1e-9);
+        assert_eq!(last.altitude(), 11.5);
+        assert_eq!(last.speed2d, 2.5);
+        assert_eq!(last.speed3d, 35.);
+        assert_eq!(last.dop, 1.5);
+        assert_eq!(last.fix, 3);
+    }
+
+    #[test]
+    fn gps5_expanded_no_gpsu() {
+        let strm = timed(gps5_strm(None, None, vec![vec![0; 5]]), 0., 1.);
+        assert!(GoProPoint::from_gps5_expanded(&strm).is_none());
+    }
+}
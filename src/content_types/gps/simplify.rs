@@ -0,0 +1,346 @@
+//! Track simplification. Unlike `Gps::downsample()`, which averages
+//! fixed-size chunks of points, geometric simplification keeps corners
+//! and removes redundant points along straight lines.
+//! Returned points are a subset of the original points.
+//!
+//! Distances are calculated on a local equirectangular projection,
+//! which is accurate enough for the extent of a typical GoPro recording.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use time::Duration;
+
+use super::{GoProPoint, Gps};
+
+/// Mean earth radius in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Simplification method for `Gps::simplify()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Simplification {
+    /// Ramer–Douglas–Peucker. Removes points that deviate less than
+    /// tolerance in meters from a line between kept points.
+    DouglasPeucker(f64),
+    /// Visvalingam–Whyatt. Iteratively removes the point forming
+    /// the smallest triangle with its neighbours, until all triangles
+    /// have an area of at least tolerance squared (tolerance in meters).
+    VisvalingamWhyatt(f64),
+    /// Keeps at most one point per time interval,
+    /// based on point timestamps.
+    Interval(Duration),
+}
+
+/// Points to keep regardless of simplification.
+/// The first and last point are always kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimplifyOptions {
+    /// Keep points where satellite lock (`GoProPoint::fix`) changes.
+    pub keep_fix_changes: bool,
+    /// Keep points where DOP (`GoProPoint::dop`) has changed
+    /// by at least this much since the last kept DOP change.
+    pub keep_dop_changes: Option<f64>,
+}
+
+impl SimplifyOptions {
+    /// Returns indices of points that must be kept.
+    fn forced(&self, points: &[GoProPoint]) -> Vec<bool> {
+        let mut forced = vec![false; points.len()];
+        if let Some(f) = forced.first_mut() {
+            *f = true;
+        }
+        if let Some(f) = forced.last_mut() {
+            *f = true;
+        }
+
+        let mut dop = points.first().map(|p| p.dop).unwrap_or_default();
+        for (i, pair) in points.windows(2).enumerate() {
+            let (a, b) = (&pair[0], &pair[1]);
+            if self.keep_fix_changes && a.fix != b.fix {
+                forced[i + 1] = true;
+            }
+            if let Some(threshold) = self.keep_dop_changes && (b.dop - dop).abs() >= threshold {
+                forced[i + 1] = true;
+                dop = b.dop;
+            }
+        }
+
+        forced
+    }
+}
+
+/// Local equirectangular projection to meters,
+/// relative to the first point.
+pub(super) struct LocalProjection {
+    latitude: f64,
+    longitude: f64,
+    scale_x: f64,
+}
+
+impl LocalProjection {
+    pub(super) fn new(points: &[GoProPoint]) -> Option<Self> {
+        let origin = points.first()?;
+        let mean_lat = points.iter().map(|p| p.latitude()).sum::<f64>() / points.len() as f64;
+        Some(Self {
+            latitude: origin.latitude(),
+            longitude: origin.longitude(),
+            scale_x: EARTH_RADIUS * mean_lat.to_radians().cos(),
+        })
+    }
+
+    /// Returns `(x, y)` in meters for point.
+    pub(super) fn to_xy(&self, point: &GoProPoint) -> (f64, f64) {
+        (
+            (point.longitude() - self.longitude).to_radians() * self.scale_x,
+            (point.latitude() - self.latitude).to_radians() * EARTH_RADIUS,
+        )
+    }
+
+    /// Returns `(latitude, longitude)` for `(x, y)` in meters.
+    pub(super) fn to_lat_lon(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.latitude + (y / EARTH_RADIUS).to_degrees(),
+            self.longitude + (x / self.scale_x).to_degrees(),
+        )
+    }
+}
+
+/// Projects points to meters, relative to the first point.
+fn project(points: &[GoProPoint]) -> Vec<(f64, f64)> {
+    match LocalProjection::new(points) {
+        Some(projection) => points.iter().map(|p| projection.to_xy(p)).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Perpendicular distance from `p` to segment `a`-`b`.
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = match len2 > 0. {
+        true => (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0., 1.),
+        false => 0.,
+    };
+    (p.0 - (a.0 + t * dx)).hypot(p.1 - (a.1 + t * dy))
+}
+
+/// Ramer–Douglas–Peucker between forced points.
+fn douglas_peucker(xy: &[(f64, f64)], keep: &mut [bool], tolerance: f64) {
+    let anchors: Vec<usize> = (0..keep.len()).filter(|i| keep[*i]).collect();
+    let mut stack: Vec<(usize, usize)> = anchors.windows(2)
+        .map(|w| (w[0], w[1]))
+        .collect();
+
+    while let Some((first, last)) = stack.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(xy[i], xy[first], xy[last])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, distance)) = farthest && distance >= tolerance {
+            keep[index] = true;
+            stack.push((first, index));
+            stack.push((index, last));
+        }
+    }
+}
+
+/// Candidate for removal, ordered by smallest area first.
+struct Candidate {
+    area: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed for min-heap
+        other.area.total_cmp(&self.area)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// Visvalingam–Whyatt. Forced points are never removed.
+fn visvalingam_whyatt(xy: &[(f64, f64)], keep: &mut [bool], forced: &[bool], min_area: f64) {
+    let n = xy.len();
+    if n < 3 {
+        return
+    }
+    let mut prev: Vec<usize> = (0..n).map(|i| i.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (0..n).map(|i| (i + 1).min(n - 1)).collect();
+    let triangle = |a: usize, b: usize, c: usize| -> f64 {
+        let (a, b, c) = (xy[a], xy[b], xy[c]);
+        ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.
+    };
+
+    let mut areas: Vec<f64> = (0..n)
+        .map(|i| match forced[i] {
+            true => f64::INFINITY,
+            false => triangle(prev[i], i, next[i]),
+        })
+        .collect();
+    let mut heap: BinaryHeap<Candidate> = (0..n)
+        .filter(|i| !forced[*i])
+        .map(|index| Candidate { area: areas[index], index })
+        .collect();
+
+    while let Some(Candidate { area, index }) = heap.pop() {
+        // Stale entry
+        if !keep[index] || area != areas[index] {
+            continue
+        }
+        if area >= min_area {
+            break
+        }
+        keep[index] = false;
+        let (p, q) = (prev[index], next[index]);
+        next[p] = q;
+        prev[q] = p;
+        // Recalculate neighbours, never below the removed area
+        for i in [p, q] {
+            if !forced[i] {
+                areas[i] = triangle(prev[i], i, next[i]).max(area);
+                heap.push(Candidate { area: areas[i], index: i });
+            }
+        }
+    }
+}
+
+/// Keeps first point per interval, and forced points.
+fn interval(points: &[GoProPoint], keep: &mut [bool], interval: Duration) {
+    let mut last: Option<Duration> = None;
+    for (i, p) in points.iter().enumerate() {
+        if keep[i] || last.is_none_or(|t| p.timestamp >= t + interval) {
+            keep[i] = true;
+            last = Some(p.timestamp);
+        }
+    }
+}
+
+impl Gps {
+    /// Returns simplified track, with points selected by
+    /// geometry (`Simplification::DouglasPeucker`,
+    /// `Simplification::VisvalingamWhyatt`), or by time
+    /// (`Simplification::Interval`).
+    /// First and last points, and points set in `options`,
+    /// are always kept.
+    pub fn simplify(&self, method: Simplification, options: &SimplifyOptions) -> Self {
+        let points = self.points();
+        let forced = options.forced(points);
+
+        let keep = match method {
+            Simplification::DouglasPeucker(tolerance) => {
+                let mut keep = forced.to_owned();
+                douglas_peucker(&project(points), &mut keep, tolerance);
+                keep
+            },
+            Simplification::VisvalingamWhyatt(tolerance) => {
+                let mut keep = vec![true; points.len()];
+                visvalingam_whyatt(&project(points), &mut keep, &forced, tolerance * tolerance);
+                keep
+            },
+            Simplification::Interval(duration) => {
+                let mut keep = forced.to_owned();
+                interval(points, &mut keep, duration);
+                keep
+            },
+        };
+
+        Self::new(points.iter()
+            .zip(keep)
+            .filter_map(|(p, k)| k.then(|| p.to_owned()))
+            .collect())
+    }
+
+    /// Mutably simplify track. See `Gps::simplify()`.
+    pub fn simplify_mut(&mut self, method: Simplification, options: &SimplifyOptions) {
+        *self = self.simplify(method, options);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::{Simplification, SimplifyOptions};
+    use crate::{GoProPoint, Gps};
+
+    /// Degrees latitude per meter.
+    const DEG_PER_M: f64 = 1. / 111_195.;
+
+    fn point(seconds: i64, north: f64, east: f64) -> GoProPoint {
+        GoProPoint {
+            timestamp: Duration::seconds(seconds),
+            ..GoProPoint::default()
+        }.with_lat_lon(north * DEG_PER_M, east * DEG_PER_M)
+    }
+
+    /// 10 m north in 10 steps, then 10 m east in 10 steps.
+    fn corner() -> Gps {
+        Gps::new((0..=20)
+            .map(|i| match i {
+                0..=10 => point(i, i as f64, 0.),
+                _ => point(i, 10., (i - 10) as f64),
+            })
+            .collect())
+    }
+
+    fn methods(tolerance: f64) -> [Simplification; 2] {
+        [
+            Simplification::DouglasPeucker(tolerance),
+            Simplification::VisvalingamWhyatt(tolerance),
+        ]
+    }
+
+    fn timestamps(gps: &Gps) -> Vec<i64> {
+        gps.iter().map(|p| p.timestamp.whole_seconds()).collect()
+    }
+
+    #[test]
+    fn simplify_collinear() {
+        let gps = corner();
+        for method in methods(1.) {
+            let simplified = gps.simplify(method, &SimplifyOptions::default());
+            assert_eq!(timestamps(&simplified), vec![0, 10, 20], "{method:?}");
+        }
+    }
+
+    #[test]
+    fn simplify_endpoints() {
+        let gps = corner();
+        for method in methods(100.) {
+            let simplified = gps.simplify(method, &SimplifyOptions::default());
+            assert_eq!(simplified.first(), gps.first());
+            assert_eq!(simplified.last(), gps.last());
+            assert_eq!(simplified.len(), 2, "{method:?}");
+        }
+    }
+
+    #[test]
+    fn simplify_zero_tolerance() {
+        let gps = corner();
+        for method in methods(0.) {
+            assert_eq!(gps.simplify(method, &SimplifyOptions::default()), gps, "{method:?}");
+        }
+    }
+
+    #[test]
+    fn simplify_short() {
+        for n in 0..=2 {
+            let gps = Gps::new((0..n).map(|i| point(i, i as f64, 0.)).collect());
+            for method in methods(1.).into_iter().chain([Simplification::Interval(Duration::seconds(5))]) {
+                assert_eq!(gps.simplify(method, &SimplifyOptions::default()), gps, "{method:?}");
+            }
+        }
+    }
+}
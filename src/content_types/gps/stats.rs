@@ -0,0 +1,237 @@
+//! Aggregate statistics for GPS logs. See `Gps::stats()`.
+
+use time::Duration;
+
+use super::Gps;
+
+/// Thresholds for `Gps::stats_with_options()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsOptions {
+    /// Minimum altitude change in meters before ascent or descent
+    /// is counted. Filters out altitude noise that would otherwise
+    /// add up to large elevation gains, even when stationary.
+    pub hysteresis: f64,
+    /// Minimum speed in m/s for an interval between two points
+    /// to be counted as moving time.
+    pub moving_speed: f64,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        Self {
+            hysteresis: 5.0,
+            moving_speed: 0.5,
+        }
+    }
+}
+
+/// Aggregate statistics for a GPS log.
+/// Distances are in meters, speeds in m/s.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GpsStats {
+    /// Number of points.
+    pub points: usize,
+    /// Total 2D (horizontal) distance.
+    pub distance2d: f64,
+    /// Total 3D distance, including altitude changes.
+    pub distance3d: f64,
+    /// Total ascent, with hysteresis.
+    pub ascent: f64,
+    /// Total descent, with hysteresis.
+    pub descent: f64,
+    /// Min altitude, above the WGS84 ellipsoid or mean sea level
+    /// depending on device (see `AltitudeReference`).
+    pub altitude_min: f64,
+    /// Max altitude, with the same reference as `altitude_min`.
+    pub altitude_max: f64,
+    /// Max reported (logged) 2D speed.
+    pub speed2d_max: f64,
+    /// Average reported (logged) 2D speed.
+    pub speed2d_avg: f64,
+    /// Max reported (logged) 3D speed.
+    pub speed3d_max: f64,
+    /// Average reported (logged) 3D speed.
+    pub speed3d_avg: f64,
+    /// Max speed derived from positions,
+    /// i.e. distance between consecutive points over time.
+    /// Sensitive to position noise.
+    pub derived_speed_max: f64,
+    /// Average speed derived from positions,
+    /// i.e. total 2D distance over duration.
+    pub derived_speed_avg: f64,
+    /// Time between first and last point.
+    pub duration: Duration,
+    /// Time with a reported 2D speed at or above
+    /// `StatsOptions::moving_speed`.
+    pub moving_time: Duration,
+    /// Time with a reported 2D speed below
+    /// `StatsOptions::moving_speed`.
+    pub stopped_time: Duration,
+    /// Bounding box. See `Gps::bounds()`.
+    pub bounds: Option<[(f64, f64); 4]>,
+}
+
+impl Gps {
+    /// Returns aggregate statistics for GPS log,
+    /// with default thresholds (5 meter hysteresis for ascent/descent,
+    /// moving at or above 0.5 m/s).
+    ///
+    /// Note that all points are used, bad and good.
+    /// Use `Gps::prune()` first to exclude points without
+    /// satellite lock.
+    pub fn stats(&self) -> GpsStats {
+        self.stats_with_options(&StatsOptions::default())
+    }
+
+    /// Returns aggregate statistics for GPS log,
+    /// with custom thresholds.
+    pub fn stats_with_options(&self, options: &StatsOptions) -> GpsStats {
+        let (Some(first), Some(last)) = (self.first(), self.last()) else {
+            return GpsStats::default()
+        };
+
+        let mut stats = GpsStats {
+            points: self.len(),
+            altitude_min: first.altitude(),
+            altitude_max: first.altitude(),
+            duration: last.timestamp - first.timestamp,
+            bounds: self.bounds(),
+            ..GpsStats::default()
+        };
+
+        // Reference altitude for hysteresis
+        let mut reference = first.altitude();
+
+        for p in self.iter() {
+            stats.altitude_min = stats.altitude_min.min(p.altitude());
+            stats.altitude_max = stats.altitude_max.max(p.altitude());
+            stats.speed2d_max = stats.speed2d_max.max(p.speed2d);
+            stats.speed3d_max = stats.speed3d_max.max(p.speed3d);
+            stats.speed2d_avg += p.speed2d;
+            stats.speed3d_avg += p.speed3d;
+
+            let delta = p.altitude() - reference;
+            if delta >= options.hysteresis {
+                stats.ascent += delta;
+                reference = p.altitude();
+            } else if -delta >= options.hysteresis {
+                stats.descent -= delta;
+                reference = p.altitude();
+            }
+        }
+        stats.speed2d_avg /= stats.points as f64;
+        stats.speed3d_avg /= stats.points as f64;
+
+        for pair in self.points().windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let distance = a.haversine(b);
+            stats.distance2d += distance;
+            stats.distance3d += distance.hypot(b.altitude() - a.altitude());
+
+            let interval = b.timestamp - a.timestamp;
+            if interval > Duration::ZERO {
+                stats.derived_speed_max = stats.derived_speed_max
+                    .max(distance / interval.as_seconds_f64());
+                match (a.speed2d + b.speed2d) / 2. >= options.moving_speed {
+                    true => stats.moving_time += interval,
+                    false => stats.stopped_time += interval,
+                }
+            }
+        }
+
+        if stats.duration > Duration::ZERO {
+            stats.derived_speed_avg = stats.distance2d / stats.duration.as_seconds_f64();
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::{GpsStats, StatsOptions};
+    use crate::{GoProPoint, Gps};
+
+    /// Degrees per meter along a meridian, or the equator.
+    const DEG_PER_M: f64 = 1. / 111_195.;
+
+    fn point(seconds: i64, north: f64, east: f64, altitude: f64, speed: f64) -> GoProPoint {
+        GoProPoint {
+            speed2d: speed,
+            speed3d: speed,
+            timestamp: Duration::seconds(seconds),
+            ..GoProPoint::default()
+        }
+            .with_lat_lon(north * DEG_PER_M, east * DEG_PER_M)
+            .with_altitude(altitude)
+    }
+
+    /// 300 m north, 400 m east, then stationary.
+    fn track() -> Gps {
+        Gps::new(vec![
+            point(0, 0., 0., 100., 10.),
+            point(30, 300., 0., 110., 10.),
+            point(70, 300., 400., 104., 10.),
+            point(80, 300., 400., 98., 0.),
+            point(90, 300., 400., 98., 0.),
+        ])
+    }
+
+    /// Relative difference below 0.5%, to allow for
+    /// the earth radius used for haversine distance.
+    fn close(value: f64, expected: f64) -> bool {
+        (value - expected).abs() <= expected.abs() * 0.005
+    }
+
+    #[test]
+    fn stats_track() {
+        let stats = track().stats();
+
+        assert_eq!(stats.points, 5);
+        assert!(close(stats.distance2d, 700.), "{}", stats.distance2d);
+        // hypot(300, 10) + hypot(400, 6) + 6
+        assert!(close(stats.distance3d, 706.21), "{}", stats.distance3d);
+        // +10, then -6 and -6, each above 5 m hysteresis
+        assert_eq!(stats.ascent, 10.);
+        assert_eq!(stats.descent, 12.);
+        assert_eq!(stats.altitude_min, 98.);
+        assert_eq!(stats.altitude_max, 110.);
+        assert_eq!(stats.speed2d_max, 10.);
+        assert_eq!(stats.speed2d_avg, 6.);
+        assert!(close(stats.derived_speed_max, 10.));
+        assert!(close(stats.derived_speed_avg, 700. / 90.));
+        assert_eq!(stats.duration, Duration::seconds(90));
+        assert_eq!(stats.moving_time, Duration::seconds(80));
+        assert_eq!(stats.stopped_time, Duration::seconds(10));
+    }
+
+    #[test]
+    fn stats_hysteresis() {
+        let options = StatsOptions {
+            hysteresis: 11.,
+            ..StatsOptions::default()
+        };
+        let stats = track().stats_with_options(&options);
+        // No altitude differs from the reference (100 m) by 11 m or more
+        assert_eq!(stats.ascent, 0.);
+        assert_eq!(stats.descent, 0.);
+    }
+
+    #[test]
+    fn stats_bounds() {
+        let (north, east) = (300. * DEG_PER_M, 400. * DEG_PER_M);
+        let expected = [(0., 0.), (0., east), (north, 0.), (north, east)];
+        assert_eq!(track().bounds(), Some(expected));
+        assert_eq!(track().stats().bounds, Some(expected));
+    }
+
+    #[test]
+    fn stats_empty() {
+        let gps = Gps::default();
+        assert_eq!(gps.stats(), GpsStats::default());
+        assert_eq!(gps.bounds(), None);
+    }
+}
@@ -11,6 +11,7 @@ use crate::{ImuSample, ImuOrientation, ImuQuantifier};
 /// - Gyroscope, fields are rotation (rad/s).
 /// - Gravity vector, fields are direction of gravity in relation to camera angle.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Imu {
     /// Camera device name
     pub device: DeviceName,
@@ -238,25 +239,51 @@ impl Imu {
         None
     }
 
-    /// Generate sample time offsets in seconds.
+    /// Generate sample time offsets in seconds,
+    /// relative to video start, with samples spread evenly
+    /// across the duration.
+    /// For timestamps derived from `TSMP`, `STMP` and `TIMO`,
+    /// see `Gpmf::timed_samples()`.
     pub fn sample_offsets(&self) -> Vec<f64> {
         if let Some(sample_duration) = self.sample_duration() && let Some(timestamp) = self.timestamp {
-            // let durs = vec![sample_duration; self.samples.len()];
-            // let sample_durations: Vec<f64> = durs.into_iter().scan(0., |state, sdur| state + sdur).collect();
             let timestamp_seconds = timestamp.as_seconds_f64();
-            let sample_durations: Vec<f64> = (0..self.samples.len())
-                .into_iter()
-                .scan(0., |state, _i| {
-                    *state = *state + sample_duration + timestamp_seconds;
-                    Some(*state)
-                }).collect();
-            assert!(sample_durations.len() == self.samples.len());
-
-            return sample_durations
+            return (0..self.samples.len())
+                .map(|i| timestamp_seconds + i as f64 * sample_duration)
+                .collect()
         }
         Vec::new()
     }
 
+    /// Returns samples with a time within `start` (inclusive)
+    /// to `end` (exclusive), relative to video start,
+    /// with sample times as in `Imu::sample_offsets()`.
+    /// `timestamp` and `duration` are set to match
+    /// the remaining samples.
+    pub fn slice(&self, start: Duration, end: Duration) -> Self {
+        let (start, end) = (start.as_seconds_f64(), end.as_seconds_f64());
+        let offsets = self.sample_offsets();
+        let indices: Vec<usize> = offsets.iter()
+            .enumerate()
+            .filter(|(_, t)| **t >= start && **t < end)
+            .map(|(i, _)| i)
+            .collect();
+
+        let samples: Vec<ImuSample> = indices.iter()
+            .map(|i| self.samples[*i].to_owned())
+            .collect();
+        let timestamp = indices.first()
+            .map(|i| Duration::seconds_f64(offsets[*i]));
+        let duration = self.sample_duration()
+            .map(|d| Duration::seconds_f64(d * samples.len() as f64));
+
+        Self {
+            samples,
+            timestamp,
+            duration,
+            ..self.to_owned()
+        }
+    }
+
     pub fn as_datatype(&self) -> DataType {
         self.sensor.as_datatype(&self.device)
     }
@@ -523,3 +550,64 @@ fn mean_value(values: &[f64]) -> f64 {
 fn median_value(values: &[f64]) {
 
 }
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use crate::{Imu, ImuSample};
+
+    /// Four samples at 1.0, 1.25, 1.5 and 1.75 s.
+    fn imu() -> Imu {
+        Imu {
+            samples: (0..4)
+                .map(|i| ImuSample { x: i as f64, y: 0., z: 0. })
+                .collect(),
+            timestamp: Some(Duration::seconds(1)),
+            duration: Some(Duration::seconds(1)),
+            ..Imu::default()
+        }
+    }
+
+    #[test]
+    fn imu_sample_offsets() {
+        // First sample at timestamp, not after the first sample duration
+        assert_eq!(imu().sample_offsets(), vec![1., 1.25, 1.5, 1.75]);
+
+        let untimed = Imu {
+            duration: None,
+            ..imu()
+        };
+        assert!(untimed.sample_offsets().is_empty());
+    }
+
+    #[test]
+    fn imu_slice() {
+        let sliced = imu().slice(Duration::seconds_f64(1.25), Duration::seconds_f64(1.75));
+
+        // Start inclusive, end exclusive
+        assert_eq!(sliced.x(), vec![1., 2.]);
+        assert_eq!(sliced.timestamp, Some(Duration::seconds_f64(1.25)));
+        assert_eq!(sliced.duration, Some(Duration::seconds_f64(0.5)));
+        assert_eq!(sliced.sample_offsets(), vec![1.25, 1.5]);
+    }
+
+    #[test]
+    fn imu_slice_bounds() {
+        let sliced = imu().slice(Duration::ZERO, Duration::seconds(5));
+        assert_eq!(sliced.x(), imu().x());
+        assert_eq!(sliced.timestamp, imu().timestamp);
+        assert_eq!(sliced.duration, imu().duration);
+
+        let sliced = imu().slice(Duration::seconds(2), Duration::seconds(3));
+        assert!(sliced.samples.is_empty());
+        assert_eq!(sliced.timestamp, None);
+
+        // Untimed samples can not be sliced
+        let untimed = Imu {
+            timestamp: None,
+            ..imu()
+        };
+        assert!(untimed.slice(Duration::ZERO, Duration::seconds(5)).samples.is_empty());
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt::Display;
 use crate::{DataType, DeviceName};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImuType {
     Accelerometer,
     // AccelerometerMagnitude,
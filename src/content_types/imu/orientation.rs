@@ -6,6 +6,7 @@
 /// stored according to the right-hand
 /// rule.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImuOrientation {
     XYZ,
     XZY,
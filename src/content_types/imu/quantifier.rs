@@ -1,6 +1,7 @@
 use crate::ImuType;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImuQuantifier {
     Acceleration,
     Rotation,
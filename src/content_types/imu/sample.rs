@@ -11,6 +11,7 @@ use super::ImuOrientation;
 /// - Gyroscrope (rotation, rad/s)
 /// - Gravity vector (direction of gravity)
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImuSample {
     pub x: f64,
     pub y: f64,
@@ -4,12 +4,14 @@ use time::{OffsetDateTime, PrimitiveDateTime, format_description};
 
 use crate::GpmfError;
 
+pub mod csv;
 pub mod data_type;
 pub mod gps;
 pub mod imu;
 
+pub use csv::{CsvColumn, CsvOptions, CsvRow, ToCsv};
 pub use data_type::DataType;
-pub use gps::{GoProPoint, Gps};
+pub use gps::{AltitudeReference, CleanOptions, CleanReport, GeoJsonKind, GoProPoint, Gps, GpsStats, Rejection, Simplification, SimplifyOptions, Sport, StatsOptions};
 pub use imu::{ImuOrientation, Imu, ImuQuantifier, ImuSample, ImuType};
 
 /// String representation for datetime objects.
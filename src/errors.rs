@@ -13,6 +13,8 @@ pub enum GpmfError {
     JpegError(jpegiter::JpegError),
     #[cfg(feature = "gpx")]
     GpxError(gpx::errors::GpxError),
+    #[cfg(feature = "kmz")]
+    ZipError(zip::result::ZipError),
     /// Failed to locate GoPro offsets in MP4.
     // NoMp4Offsets(mp4iter::FourCC),
     NoMp4Offsets(String),
@@ -48,8 +50,25 @@ pub enum GpmfError {
     UnknownBaseType(u8),
     /// Missing type definition for Complex type (`63`/`?`)
     MissingComplexType,
+    /// Failed to decode Huffman compressed data (`35`/`#`)
+    DecompressError(String),
+    /// Failed to encode GPMF data, e.g. values that
+    /// do not fit the stream header.
+    EncodeError(String),
     /// Exceeded recurse depth when parsing GPMF into `Stream`s
     RecurseDepthExceeded((usize, usize)),
+    /// Exceeded max number of `Value`s
+    /// when parsing GPMF into `Stream`s.
+    MaxValuesExceeded{max: usize, got: usize},
+    /// KLV data size exceeds max size.
+    MaxKlvSizeExceeded{max: usize, got: usize},
+    /// KLV data size exceeds remaining bytes in
+    /// the parent container or input.
+    ReadLimitExceeded{size: usize, remaining: usize},
+    /// Invalid selector query.
+    InvalidSelector(String),
+    /// Sample rate must be a positive number.
+    InvalidSampleRate(f64),
     /// Invalid FourCC. For detecting `&[0, 0, 0, 0]`.
     /// E.g. GoPro `udta` atom contains
     /// mainly undocumented GPMF data and is padded with
@@ -102,6 +121,8 @@ impl fmt::Display for GpmfError {
             GpmfError::JpegError(err) => write!(f, "{err}"),
             #[cfg(feature = "gpx")]
             GpmfError::GpxError(err) => write!(f, "GPX error: {err}"),
+            #[cfg(feature = "kmz")]
+            GpmfError::ZipError(err) => write!(f, "Zip error: {err}"),
             // GpmfError::NoMp4Offsets => write!(f, "Failed to locate GoPro GPMF offsets in MP4."),
             GpmfError::NoMp4Offsets(name) => write!(f, "Failed to locate GoPro GPMF offsets in MP4 for handler with name '{name}'."),
             GpmfError::BinReadError(err) => write!(f, "{err}"),
@@ -120,7 +141,14 @@ impl fmt::Display for GpmfError {
             GpmfError::MaxFileSizeExceeded {max, got, path} => write!(f, "{} ({got} bytes) exceeds maximum file size of {max}.", path.display()),
             GpmfError::UnknownBaseType(bt) => write!(f, "Unknown base type {}/'{}'", bt, *bt as char),
             GpmfError::MissingComplexType => write!(f, "Missing type definitions for complex type '?'"),
+            GpmfError::DecompressError(msg) => write!(f, "Failed to decode compressed data: {msg}"),
+            GpmfError::EncodeError(msg) => write!(f, "Failed to encode GPMF data: {msg}"),
             GpmfError::RecurseDepthExceeded((depth, max)) => write!(f, "Recurse depth {depth} exceeds max recurse depth {max}"),
+            GpmfError::MaxValuesExceeded{max, got} => write!(f, "Number of values {got} exceeds max {max}"),
+            GpmfError::MaxKlvSizeExceeded{max, got} => write!(f, "KLV size {got} bytes exceeds max {max} bytes"),
+            GpmfError::ReadLimitExceeded{size, remaining} => write!(f, "KLV size {size} bytes exceeds remaining {remaining} bytes"),
+            GpmfError::InvalidSelector(msg) => write!(f, "Invalid selector: {msg}"),
+            GpmfError::InvalidSampleRate(rate) => write!(f, "Invalid sample rate {rate}, must be a positive number"),
             GpmfError::InvalidFourCC => write!(f, "Invalid FourCC"),
             GpmfError::NoMuid => write!(f, "No MUID found"),
             GpmfError::NoGumi => write!(f, "No GUMI found"),
@@ -220,3 +248,11 @@ impl From<gpx::errors::GpxError> for GpmfError {
         GpmfError::GpxError(err)
     }
 }
+
+#[cfg(feature = "kmz")]
+/// Converts zip::result::ZipError to GpmfError
+impl From<zip::result::ZipError> for GpmfError {
+    fn from(err: zip::result::ZipError) -> GpmfError {
+        GpmfError::ZipError(err)
+    }
+}
@@ -1,4 +1,6 @@
-use std::{path::Path, ffi::OsStr};
+use std::{ffi::OsStr, fs::File, io::Write, path::Path};
+
+use crate::GpmfError;
 
 /// Matches file extension of `path`
 pub(crate) fn has_extension_single(path: &Path, ext: &str) -> bool {
@@ -37,3 +39,9 @@ pub(crate) fn fileext_to_lcstring(path: &Path) -> Option<String> {
 pub(crate) fn filestem_to_string(path: &Path) -> Option<String> {
     Some(path.file_stem()?.to_str()?.to_string())
 }
+
+/// Writes `bytes` to a new file. Fails if `path` exists.
+pub(crate) fn write_new(path: &Path, bytes: &[u8]) -> Result<(), GpmfError> {
+    let mut file = File::create_new(path)?;
+    Ok(file.write_all(bytes)?)
+}
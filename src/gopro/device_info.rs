@@ -1,6 +1,7 @@
 use crate::{DeviceName, GpmfError, gopro::GoProMeta};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeviceInfo {
     pub name: DeviceName,
     // id: DeviceId,
@@ -7,6 +7,7 @@ use crate::{GpmfError, gopro::GoProMeta};
 /// GoPro camera model.
 /// Only Hero5 and later have GPMF data.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeviceName {
     #[default]
     Hero2014,
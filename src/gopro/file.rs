@@ -22,6 +22,7 @@ use crate::{
     Cpid,
     DeviceInfo,
     DeviceName,
+    FrameMap,
     GOPRO_MIN_WIDTH_HEIGHT,
     Gpmf,
     GpmfError,
@@ -32,7 +33,7 @@ use crate::{
     types::{Gumi, Muid},
 };
 
-use super::GoProMeta;
+use super::{GoProMeta, Timecode};
 
 /// Represents an original, unedited GoPro MP4-file.
 ///
@@ -69,6 +70,7 @@ use super::GoProMeta;
 ///         - LRV has a value
 ///         - `GUMI` matches between MP4 and LRV
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GoProFile {
     /// GoPro device info, use of e.g. MUID
     /// and present GPMF data may differ
@@ -122,6 +124,9 @@ pub struct GoProFile {
     /// with other low-res/LRV clips).
     pub(crate) resolution: (u16, u16),
     pub(crate) metadata: GoProMeta,
+    /// SMPTE timecode from timecode track (`GoPro TCD`),
+    /// if present.
+    pub(crate) timecode: Option<Timecode>,
 }
 
 #[cfg(feature = "gpx")]
@@ -172,6 +177,9 @@ impl GoProFile {
         file.cpid = file.metadata.cpid();
         file.cpin = file.metadata.cpin();
 
+        // Not all models/modes log timecode
+        file.timecode = Timecode::from_mp4(&mut mp4).ok();
+
         // Set session ID, derived from MUID, GUMI, or CPID
         // depending on model.
         file.session_id = file.session_id_u8()
@@ -384,6 +392,12 @@ impl GoProFile {
         self.time_first_frame
     }
 
+    /// Returns SMPTE timecode for clip,
+    /// if a timecode track is present.
+    pub fn timecode(&self) -> Option<&Timecode> {
+        self.timecode.as_ref()
+    }
+
     pub fn start(&self) -> PrimitiveDateTime {
         self.creation_time
     }
@@ -460,6 +474,35 @@ impl GoProFile {
         Ok(gpmf.has_gps())
     }
 
+    /// Returns video frame timing,
+    /// including frame skips (`MSKP` for MP4, `LSKP` for LRV) if logged.
+    /// See `FrameMap`.
+    ///
+    /// Note that this reads all video samples and
+    /// the GPMF track from disk.
+    pub fn frames(&self) -> Result<FrameMap, GpmfError> {
+        let gpmf = self.gpmf()?;
+        let mut frames = FrameMap::new(&self.source)?;
+        frames.low_res = self.is_low_res();
+        Ok(frames.with_frame_skips(&gpmf.streams))
+    }
+
+    /// Returns zero-based frame number for
+    /// the frame displayed at `time`, relative to video start.
+    /// Reads from disk on each call,
+    /// use `GoProFile::frames()` for repeated lookups.
+    pub fn frame_at(&self, time: Duration) -> Result<Option<usize>, GpmfError> {
+        Ok(self.frames()?.frame_at(time))
+    }
+
+    /// Returns start time for zero-based frame number `frame`,
+    /// relative to video start.
+    /// Reads from disk on each call,
+    /// use `GoProFile::frames()` for repeated lookups.
+    pub fn time_of_frame(&self, frame: usize) -> Result<Option<Duration>, GpmfError> {
+        Ok(self.frames()?.time_of_frame(frame))
+    }
+
     pub fn gps(&self) -> Result<Gps, GpmfError> {
         Ok(self.gpmf()?.gps())
     }
@@ -501,7 +544,8 @@ impl Default for GoProFile {
             duration: Duration::ZERO,
             time_first_frame: Duration::ZERO,
             resolution: (u16::default(), u16::default()),
-            metadata: GoProMeta::default()
+            metadata: GoProMeta::default(),
+            timecode: None,
         }
     }
 }
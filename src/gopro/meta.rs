@@ -25,6 +25,7 @@ use crate::{
 /// but contains data such as firmware version
 /// settings and identifiers.
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GoProMeta {
     path: PathBuf,
     raw: Vec<(String, Vec<u8>)>,
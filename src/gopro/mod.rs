@@ -9,6 +9,7 @@ pub mod filetype;
 // pub mod session_old;
 pub mod session;
 pub mod meta;
+pub mod timecode;
 
 pub use filetype::GoProFileType;
 // pub use file::GoProFileOld;
@@ -16,6 +17,7 @@ pub use file::GoProFile;
 // pub use session::GoProSessionOld;
 pub use session::{GoProSession, GoProMultiSession};
 pub use meta::GoProMeta;
+pub use timecode::{Smpte, Timecode};
 pub use device_id::DeviceId;
 pub use device_name::DeviceName;
 pub use device_info::DeviceInfo;
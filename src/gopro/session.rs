@@ -8,8 +8,13 @@ use mp4iter::Mp4Error;
 use time::{Duration, PrimitiveDateTime};
 use walkdir::WalkDir;
 
+#[cfg(feature = "kmz")]
+use crate::content_types::gps::write_kmz;
+
 use crate::{
+    CsvOptions,
     DeviceInfo,
+    GeoJsonKind,
     GOPRO_DATETIME_DEFAULT,
     GOPRO_VALID_EXTENSIONS,
     Gpmf,
@@ -17,11 +22,16 @@ use crate::{
     Gps,
     Imu,
     ImuType,
-    files::{filename_startswith, has_extension},
+    Sport,
+    Timestamp,
+    ToCsv,
+    content_types::gps::{fit_activity, kml_document, kml_escape, kml_placemark, tcx_activity},
+    files::{filename_startswith, has_extension, write_new},
     gopro::file::GoProFile
 };
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GoProSession{
     pub(crate) creation_time: PrimitiveDateTime,
     pub resolution: (u16, u16),
@@ -402,6 +412,157 @@ impl GoProSession {
         Ok(self.gpmf()?.gps())
     }
 
+    /// Returns GPS log for the session as GeoJSON,
+    /// either as a single `LineString` feature, or as a collection
+    /// of `Point` features. See `Gps::to_geojson()`.
+    ///
+    /// Reads from disk.
+    /// Note that this exports all points, bad and good.
+    /// Use `GoProSession::gps()`, then `Gps::prune()` and
+    /// `Gps::to_geojson()` to exclude points without satellite lock.
+    pub fn to_geojson(&self, kind: GeoJsonKind) -> Result<geojson::GeoJson, GpmfError> {
+        Ok(self.gps()?.to_geojson(kind))
+    }
+
+    /// Returns GPS log for the session as a KML document,
+    /// with one folder per clip. Each folder contains
+    /// a placemark at the first logged position (first point with
+    /// a satellite lock if there is one), described with clip name
+    /// and device info, and the clip's GPS log as `gx:Track` elements.
+    /// See `Gps::to_kml()`.
+    ///
+    /// Reads from disk.
+    pub fn to_kml(&self) -> Result<String, GpmfError> {
+        let mut folders = String::new();
+        for file in self.iter() {
+            let gpmf = file.gpmf()?;
+            let gps = gpmf.gps();
+            let altitude = gpmf.altitude_reference();
+            let name = file.basename()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let description = format!(
+                "{} (firmware {})\nCreated: {}\nDuration: {:.3}s\nPath: {}",
+                file.device.name(),
+                file.device.firmware,
+                file.creation_time(),
+                file.duration().as_seconds_f64(),
+                file.path().display()
+            );
+            let placemark = gps.iter()
+                .find(|p| p.fix >= 2)
+                .or_else(|| gps.first())
+                .map(|p| kml_placemark(&name, &description, p, altitude))
+                .unwrap_or_default();
+            folders.push_str(&format!(
+                "<Folder>\n<name>{}</name>\n{placemark}{}</Folder>\n",
+                kml_escape(&name),
+                gps.to_kml_tracks(altitude)
+            ));
+        }
+        Ok(kml_document(&self.basename().unwrap_or_default(), &folders))
+    }
+
+    /// Write session GPS log as KML file. See `GoProSession::to_kml()`.
+    ///
+    /// Reads from disk.
+    pub fn write_kml(&self, path: &Path) -> Result<(), GpmfError> {
+        write_new(path, self.to_kml()?.as_bytes())
+    }
+
+    /// Write session GPS log as KMZ file (zipped KML).
+    /// See `GoProSession::to_kml()`.
+    ///
+    /// Reads from disk.
+    #[cfg(feature = "kmz")]
+    pub fn write_kmz(&self, path: &Path) -> Result<(), GpmfError> {
+        write_kmz(path, &self.to_kml()?)
+    }
+
+    /// Returns data extracted from each clip as CSV/TSV,
+    /// with timestamps relative to session start,
+    /// as for `GoProSession::gpmf()`.
+    /// `extract` returns data for a single clip, e.g.
+    /// `|gpmf| gpmf.gps()` or `|gpmf| gpmf.imu(&ImuType::Accelerometer)`.
+    /// Source (`CsvColumn::Source`) is set to the path of each clip.
+    ///
+    /// Reads from disk.
+    pub fn to_csv<T: ToCsv>(
+        &self,
+        extract: impl Fn(&Gpmf) -> T,
+        options: &CsvOptions
+    ) -> Result<String, GpmfError> {
+        let mut csv = String::new();
+        let mut last: Option<Timestamp> = None;
+        for (i, file) in self.iter().enumerate() {
+            let mut gpmf = file.gpmf()?;
+            // Offset timestamps in the same manner as Gpmf::merge_mut()
+            if let Some(ts) = &last {
+                gpmf.offset_time(ts);
+            }
+            if let Some(ts) = gpmf.last_timestamp() {
+                last = Some(ts.to_owned());
+            }
+            let options = CsvOptions {
+                header: options.header && i == 0,
+                source: Some(file.path().display().to_string()),
+                ..options.to_owned()
+            };
+            csv.push_str(&extract(&gpmf).to_csv(&options));
+        }
+        Ok(csv)
+    }
+
+    /// Write CSV/TSV file. See `GoProSession::to_csv()`.
+    ///
+    /// Reads from disk.
+    pub fn write_csv<T: ToCsv>(
+        &self,
+        path: &Path,
+        extract: impl Fn(&Gpmf) -> T,
+        options: &CsvOptions
+    ) -> Result<(), GpmfError> {
+        write_new(path, self.to_csv(extract, options)?.as_bytes())
+    }
+
+    /// Returns GPS log for the session as a FIT activity file,
+    /// with one lap per clip. See `Gps::to_fit()`.
+    ///
+    /// Reads from disk.
+    pub fn to_fit(&self, sport: Sport) -> Result<Vec<u8>, GpmfError> {
+        let laps = self.iter()
+            .map(|file| file.gps())
+            .collect::<Result<Vec<_>, GpmfError>>()?;
+        Ok(fit_activity(&laps, sport))
+    }
+
+    /// Write session GPS log as FIT activity file,
+    /// with one lap per clip. See `Gps::to_fit()`.
+    ///
+    /// Reads from disk.
+    pub fn write_fit(&self, path: &Path, sport: Sport) -> Result<(), GpmfError> {
+        write_new(path, &self.to_fit(sport)?)
+    }
+
+    /// Returns GPS log for the session as a TCX document,
+    /// with one lap per clip. See `Gps::to_tcx()`.
+    ///
+    /// Reads from disk.
+    pub fn to_tcx(&self, sport: Sport) -> Result<String, GpmfError> {
+        let laps = self.iter()
+            .map(|file| file.gps())
+            .collect::<Result<Vec<_>, GpmfError>>()?;
+        Ok(tcx_activity(&laps, sport))
+    }
+
+    /// Write session GPS log as TCX file,
+    /// with one lap per clip. See `Gps::to_tcx()`.
+    ///
+    /// Reads from disk.
+    pub fn write_tcx(&self, path: &Path, sport: Sport) -> Result<(), GpmfError> {
+        write_new(path, self.to_tcx(sport)?.as_bytes())
+    }
+
     /// Extracts accelerometer data.
     ///
     /// Reads from disk. I.e. if you
@@ -453,6 +614,7 @@ impl GoProSession {
 /// `id` corresponds to either `MUID` or `GUMI`
 /// depending on model.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GoProMultiSession {
     /// Session ID.
     /// Derived from Either MUID, GUMI, or CPID,
@@ -0,0 +1,434 @@
+//! SMPTE timecode from the GoPro timecode track (`GoPro TCD`),
+//! e.g. for lining up multi-camera footage by timecode.
+//!
+//! The timecode track contains a single sample: the frame number
+//! for the first video frame, counted from `00:00:00:00`.
+//! Frame rate, frames per timecode second and drop-frame
+//! are read from the timecode sample description (`tmcd` in `stsd`).
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    str::FromStr,
+};
+
+use binrw::BinReaderExt;
+use mp4iter::Mp4;
+use time::Duration;
+
+use crate::{
+    GOPRO_TIMECODE_HANDLER,
+    GpmfError,
+    Timestamp,
+};
+
+/// `tmcd` flag for drop-frame timecode.
+const TMCD_DROP_FRAME: u32 = 0x0001;
+
+/// SMPTE timecode `HH:MM:SS:FF`,
+/// or `HH:MM:SS;FF` for drop-frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Smpte {
+    pub hours: u32,
+    pub minutes: u32,
+    pub seconds: u32,
+    pub frames: u32,
+    pub drop_frame: bool,
+}
+
+impl Display for Smpte {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sep = match self.drop_frame {
+            true => ';',
+            false => ':',
+        };
+        write!(f, "{:02}:{:02}:{:02}{sep}{:02}", self.hours, self.minutes, self.seconds, self.frames)
+    }
+}
+
+impl FromStr for Smpte {
+    type Err = GpmfError;
+
+    /// Parses `HH:MM:SS:FF`, or `HH:MM:SS;FF` (also `.` or `,` as
+    /// last separator) for drop-frame.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let drop_frame = s.contains([';', '.', ',']);
+        let parts = s.split([':', ';', '.', ','])
+            .map(|p| p.parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()?;
+        match parts.as_slice() {
+            [hours, minutes, seconds, frames] => Ok(Self {
+                hours: *hours,
+                minutes: *minutes,
+                seconds: *seconds,
+                frames: *frames,
+                drop_frame,
+            }),
+            _ => Err(GpmfError::ParseError),
+        }
+    }
+}
+
+/// Timecode for a GoPro clip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timecode {
+    /// Frame number for first video frame,
+    /// counted from `00:00:00:00`.
+    pub start_frame: u32,
+    /// Actual frame rate, e.g. `29.97`.
+    pub rate: f64,
+    /// Nominal (rounded) frame rate, e.g. `30`
+    /// for `29.97`. Frames per timecode second.
+    pub fps: u32,
+    /// `true` for drop-frame timecode.
+    pub drop_frame: bool,
+}
+
+impl Display for Timecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} @ {:.3} fps", self.start(), self.rate)
+    }
+}
+
+impl Timecode {
+    /// Reads timecode from GoPro MP4-file.
+    pub fn new(path: &Path) -> Result<Self, GpmfError> {
+        let mut mp4 = Mp4::new(path)?;
+        Self::from_mp4(&mut mp4)
+    }
+
+    pub(crate) fn from_mp4(mp4: &mut Mp4) -> Result<Self, GpmfError> {
+        let (flags, timescale, frame_duration, frames) = Self::sample_description(mp4.path())?;
+
+        let start_frame: u32 = {
+            let mut track = mp4.track(GOPRO_TIMECODE_HANDLER, true)?;
+            let mut sample = track.samples()
+                .next()
+                .ok_or(GpmfError::NoSamples)??;
+            sample.read_be()?
+        };
+
+        let rate = match frame_duration {
+            0 => frames as f64,
+            _ => timescale as f64 / frame_duration as f64,
+        };
+
+        Ok(Self {
+            start_frame,
+            rate,
+            fps: match frames {
+                0 => rate.round() as u32,
+                _ => frames as u32,
+            },
+            drop_frame: flags & TMCD_DROP_FRAME != 0,
+        })
+    }
+
+    /// Reads timecode sample description, the `tmcd` entry
+    /// in the `stsd` atom of the timecode track, and returns
+    /// `(flags, timescale, frame duration, number of frames)`.
+    ///
+    /// The `moov` atom is read from disk and the timecode track
+    /// is resolved via its handler type (`tmcd` in `mdia/hdlr`),
+    /// since every track has an `stsd` atom.
+    fn sample_description(path: &Path) -> Result<(u32, u32, u32, u8), GpmfError> {
+        let moov = read_moov(path)?;
+        tmcd_description(&moov)
+            .ok_or_else(|| GpmfError::NoSuchAtom("tmcd".to_owned()))
+    }
+
+    /// Frames dropped per minute for drop-frame timecode
+    /// (2 for 29.97, 4 for 59.94).
+    fn dropped(&self) -> u32 {
+        match self.drop_frame {
+            true => self.fps / 15,
+            false => 0,
+        }
+    }
+
+    /// Converts frame number to timecode.
+    pub fn to_smpte(&self, frame: u32) -> Smpte {
+        let fps = self.fps.max(1);
+        let drop = self.dropped();
+
+        // Add back numbers skipped by drop-frame counting
+        let frame = match drop {
+            0 => frame,
+            _ => {
+                let per_10_min = fps * 600 - drop * 9;
+                let per_min = fps * 60 - drop;
+                let (tens, rem) = (frame / per_10_min, frame % per_10_min);
+                let minutes = match rem > drop {
+                    true => (rem - drop) / per_min,
+                    false => 0,
+                };
+                frame + drop * 9 * tens + drop * minutes
+            }
+        };
+
+        Smpte {
+            hours: frame / (fps * 3600),
+            minutes: frame / (fps * 60) % 60,
+            seconds: frame / fps % 60,
+            frames: frame % fps,
+            drop_frame: self.drop_frame,
+        }
+    }
+
+    /// Converts timecode to frame number.
+    pub fn to_frame(&self, smpte: &Smpte) -> u32 {
+        let total_minutes = smpte.hours * 60 + smpte.minutes;
+        let frame = (total_minutes * 60 + smpte.seconds) * self.fps + smpte.frames;
+        frame.saturating_sub(self.dropped() * (total_minutes - total_minutes / 10))
+    }
+
+    /// Returns timecode for first video frame.
+    pub fn start(&self) -> Smpte {
+        self.to_smpte(self.start_frame)
+    }
+
+    /// Returns timecode for time relative to video start.
+    pub fn at(&self, time: Duration) -> Smpte {
+        // Small offset to avoid rounding down exact frame times
+        let frames = (time.as_seconds_f64() * self.rate + 1e-6).floor().max(0.) as u32;
+        self.to_smpte(self.start_frame + frames)
+    }
+
+    /// Returns time relative to video start for timecode.
+    /// Negative if timecode precedes first frame.
+    pub fn time_of(&self, smpte: &Smpte) -> Duration {
+        let frames = self.to_frame(smpte) as i64 - self.start_frame as i64;
+        Duration::seconds_f64(frames as f64 / self.rate)
+    }
+
+    /// Returns timecode for telemetry timestamp (start of `DEVC`).
+    pub fn from_timestamp(&self, timestamp: &Timestamp) -> Smpte {
+        self.at(timestamp.relative)
+    }
+
+    /// Returns telemetry timestamp for timecode,
+    /// with duration set to one frame.
+    pub fn to_timestamp(&self, smpte: &Smpte) -> Timestamp {
+        Timestamp {
+            relative: self.time_of(smpte),
+            duration: Duration::seconds_f64(1. / self.rate),
+        }
+    }
+}
+
+/// Returns child atoms as `(FourCC, payload)`.
+/// Iteration stops at the first invalid atom size.
+fn atoms(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as u64;
+        let name = rest.get(4..8)?;
+        let (header, size) = match size {
+            // Atom extends to end of parent
+            0 => (8, rest.len() as u64),
+            // 64-bit size
+            1 => (16, u64::from_be_bytes(rest.get(8..16)?.try_into().ok()?)),
+            _ => (8, size),
+        };
+        let size = usize::try_from(size).ok()?;
+        let payload = rest.get(header..size)?;
+        rest = &rest[size..];
+        Some((name, payload))
+    })
+}
+
+/// Returns payload for first child atom with FourCC `name`.
+fn child<'a>(data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data)
+        .find(|(n, _)| n == name)
+        .map(|(_, payload)| payload)
+}
+
+/// Reads `moov` atom payload from MP4 file.
+fn read_moov(path: &Path) -> Result<Vec<u8>, GpmfError> {
+    let mut file = File::open(path)?;
+    loop {
+        let mut header = [0_u8; 8];
+        file.read_exact(&mut header)?;
+        let (size, name) = header.split_at(4);
+        let (header_size, size) = match u32::from_be_bytes(size.try_into().unwrap()) {
+            1 => {
+                let mut large = [0_u8; 8];
+                file.read_exact(&mut large)?;
+                (16, u64::from_be_bytes(large))
+            },
+            size => (8, size as u64),
+        };
+        if size < header_size {
+            return Err(GpmfError::NoSuchAtom("moov".to_owned()))
+        }
+        if name == b"moov" {
+            let mut moov = Vec::new();
+            file.take(size - header_size).read_to_end(&mut moov)?;
+            return Ok(moov)
+        }
+        file.seek(SeekFrom::Current((size - header_size) as i64))?;
+    }
+}
+
+/// Returns `(flags, timescale, frame duration, number of frames)`
+/// from the `tmcd` sample entry of the track with
+/// handler type `tmcd`, given the `moov` atom payload.
+///
+/// `tmcd` layout after the sample entry header:
+/// reserved (4), flags (4), timescale (4), frame duration (4),
+/// number of frames (1), reserved (1).
+fn tmcd_description(moov: &[u8]) -> Option<(u32, u32, u32, u8)> {
+    let u32_at = |data: &[u8], offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+    };
+
+    let mdia = atoms(moov)
+        .filter(|(name, _)| name == b"trak")
+        .filter_map(|(_, trak)| child(trak, b"mdia"))
+        // hdlr: version + flags (4), component type (4), handler type (4)
+        .find(|mdia| child(mdia, b"hdlr")
+            .and_then(|hdlr| hdlr.get(8..12))
+            == Some(b"tmcd".as_slice()))?;
+
+    let stsd = child(mdia, b"minf")
+        .and_then(|minf| child(minf, b"stbl"))
+        .and_then(|stbl| child(stbl, b"stsd"))?;
+
+    // stsd: version + flags (4), entry count (4), then sample entries
+    let (name, entry) = atoms(stsd.get(8..)?).next()?;
+    if name != b"tmcd" {
+        return None
+    }
+
+    // Sample entry: reserved (6), data reference index (2),
+    // then tmcd: reserved (4), flags, timescale, frame duration
+    Some((
+        u32_at(entry, 12)?,
+        u32_at(entry, 16)?,
+        u32_at(entry, 20)?,
+        *entry.get(24)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Smpte, Timecode, tmcd_description};
+
+    fn atom(name: &str, payload: &[u8]) -> Vec<u8> {
+        let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend(name.as_bytes());
+        atom.extend(payload);
+        atom
+    }
+
+    fn trak(handler: &str, entry: &[u8]) -> Vec<u8> {
+        let hdlr = [&[0_u8; 8][..], handler.as_bytes(), &[0; 12][..], &b"GoPro TCD"[..]].concat();
+        let stsd = [&[0_u8, 0, 0, 0, 0, 0, 0, 1][..], entry].concat();
+        atom("trak", &atom("mdia", &[
+            atom("mdhd", &[0; 24]),
+            atom("hdlr", &hdlr),
+            atom("minf", &atom("stbl", &atom("stsd", &stsd))),
+        ].concat()))
+    }
+
+    fn tmcd(flags: u32, timescale: u32, frame_duration: u32, frames: u8) -> Vec<u8> {
+        atom("tmcd", &[
+            &[0_u8, 0, 0, 0, 0, 0, 0, 1][..],
+            &[0; 4][..],
+            &flags.to_be_bytes()[..],
+            &timescale.to_be_bytes()[..],
+            &frame_duration.to_be_bytes()[..],
+            &[frames, 0][..],
+        ].concat())
+    }
+
+    fn smpte(s: &str) -> Smpte {
+        s.parse().unwrap()
+    }
+
+    fn ntsc(drop_frame: bool) -> Timecode {
+        Timecode {
+            start_frame: 0,
+            rate: 30000. / 1001.,
+            fps: 30,
+            drop_frame,
+        }
+    }
+
+    #[test]
+    fn tmcd_track() {
+        // Video track first, with its own stsd
+        let moov = [
+            atom("mvhd", &[0; 100]),
+            trak("vide", &atom("avc1", &[0; 78])),
+            trak("tmcd", &tmcd(1, 30000, 1001, 30)),
+        ].concat();
+        assert_eq!(tmcd_description(&moov), Some((1, 30000, 1001, 30)));
+
+        let moov = [
+            trak("vide", &atom("avc1", &[0; 78])),
+            trak("soun", &atom("mp4a", &[0; 28])),
+        ].concat();
+        assert_eq!(tmcd_description(&moov), None);
+
+        // Truncated sample entry
+        let moov = trak("tmcd", &atom("tmcd", &[0; 16]));
+        assert_eq!(tmcd_description(&moov), None);
+    }
+
+    #[test]
+    fn smpte_drop_frame() {
+        let tc = ntsc(true);
+
+        // Frames ;00 and ;01 are skipped at the start of each minute...
+        let frame = tc.to_frame(&smpte("00:00:59;29"));
+        assert_eq!(frame, 1799);
+        assert_eq!(tc.to_smpte(frame + 1), smpte("00:01:00;02"));
+        assert_eq!(tc.to_frame(&smpte("00:01:00;02")), 1800);
+
+        // ...except every tenth minute
+        let frame = tc.to_frame(&smpte("00:09:59;29"));
+        assert_eq!(frame, 17981);
+        assert_eq!(tc.to_smpte(frame + 1), smpte("00:10:00;00"));
+        let frame = tc.to_frame(&smpte("00:10:59;29"));
+        assert_eq!(tc.to_smpte(frame + 1), smpte("00:11:00;02"));
+
+        assert_eq!(tc.to_smpte(107_892), smpte("01:00:00;00"));
+        assert_eq!(tc.to_smpte(107_892).to_string(), "01:00:00;00");
+    }
+
+    #[test]
+    fn smpte_round_trip() {
+        for tc in [ntsc(true), ntsc(false)] {
+            for frame in (0..2 * 108_000).step_by(7).chain(17_975..17_990) {
+                let smpte = tc.to_smpte(frame);
+                assert!(smpte.frames < 30);
+                assert_eq!(tc.to_frame(&smpte), frame, "{smpte}");
+            }
+        }
+
+        let tc = Timecode {
+            fps: 60,
+            rate: 60000. / 1001.,
+            ..ntsc(true)
+        };
+        assert_eq!(tc.to_smpte(3600).to_string(), "00:01:00;04");
+        for frame in (0..216_000).step_by(11) {
+            assert_eq!(tc.to_frame(&tc.to_smpte(frame)), frame);
+        }
+    }
+
+    #[test]
+    fn smpte_non_drop() {
+        let tc = ntsc(false);
+        assert_eq!(tc.to_smpte(1800), smpte("00:01:00:00"));
+        assert_eq!(tc.to_frame(&smpte("01:00:00:00")), 108_000);
+        assert!(!tc.to_smpte(1800).drop_frame);
+    }
+}
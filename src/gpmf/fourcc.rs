@@ -195,6 +195,39 @@ impl Default for FourCC {
     }
 }
 
+/// Serializes as the Four CC string, e.g. `"GPS9"`,
+/// with `FourCC::Invalid` as an empty string.
+#[cfg(feature = "serde")]
+impl serde::Serialize for FourCC {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FourCC::Invalid => serializer.serialize_str(""),
+            fourcc => serializer.serialize_str(fourcc.to_str()),
+        }
+    }
+}
+
+/// Deserializes from the Four CC string, where
+/// an empty string is `FourCC::Invalid`, and
+/// undocumented Four CC are `FourCC::Other`.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FourCC {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fourcc = String::deserialize(deserializer)?;
+        if fourcc.is_empty() {
+            return Ok(FourCC::Invalid)
+        }
+        // ISO8859-1, see `FourCC::from_slice()`
+        let bytes: Vec<u8> = fourcc.chars()
+            .filter_map(|c| u8::try_from(c as u32).ok())
+            .collect();
+        match (fourcc.chars().count(), bytes.len()) {
+            (4, 4) => Ok(FourCC::from_slice(&bytes)),
+            _ => Ok(FourCC::Other(fourcc)),
+        }
+    }
+}
+
 // impl BinRead for FourCC {
 //     type Args<'a>;
 
@@ -218,7 +251,7 @@ impl FourCC {
 
     /// Generate FourCC enum from `&str`.
     // fn from_slice(slice: &[u8]) -> Result<Self, GpmfError> {
-    fn from_slice(slice: &[u8]) -> Self {
+    pub(crate) fn from_slice(slice: &[u8]) -> Self {
 
         match slice {
             // GPMF structural FourCC
@@ -441,6 +474,7 @@ impl FourCC {
             "FACE" => FourCC::FACE,
             "FCNM" => FourCC::FCNM,
             "GPS5" => FourCC::GPS5,
+            "GPS9" => FourCC::GPS9,
             "GPSF" => FourCC::GPSF,
             "GPSP" => FourCC::GPSP,
             "GPSU" => FourCC::GPSU,
@@ -590,6 +624,21 @@ impl FourCC {
         }
     }
 
+    /// Returns FourCC as raw bytes.
+    /// `FourCC::Invalid` returns `[0, 0, 0, 0]`.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let mut bytes = [0_u8; 4];
+        if !self.is_invalid() {
+            // ISO8859-1, see `FourCC::from_slice()`
+            self.to_str()
+                .chars()
+                .take(4)
+                .enumerate()
+                .for_each(|(i, c)| bytes[i] = c as u32 as u8);
+        }
+        bytes
+    }
+
     pub fn is_invalid(&self) -> bool {
         self == &FourCC::Invalid
     }
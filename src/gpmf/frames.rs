@@ -0,0 +1,300 @@
+//! Mapping between telemetry time and video frame numbers.
+//!
+//! Frame times are read from the video track sample timing
+//! (`stts` atom) and shifted by the time of the first frame
+//! (MP4 edit list), so that they share the time line of
+//! GPMF `DEVC` timestamps.
+//!
+//! Hero9 and later log frame skips for the main (`MSKP`)
+//! and low-resolution (`LSKP`) video. I.e. the number of sensor frames
+//! skipped (or duplicated) before each encoded frame, normally 0.
+//! If present these are accumulated and added to frame times.
+//!
+//! ```rs
+//! use gpmf_rs::{Gpmf, FourCC};
+//! use std::path::Path;
+//! use time::Duration;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! let frames = gpmf.frames()?;
+//! let gyro = gpmf.timed_samples(&FourCC::GYRO);
+//! if let (Some(n), Some(gyro)) = (frames.frame_at(Duration::seconds(10)), gyro.first()) {
+//!     println!("Frame {n} at {:?}", frames.time_of_frame(n));
+//!     println!("{:?}", frames.samples(n, gyro));
+//! }
+//! ```
+
+use std::path::Path;
+
+use mp4iter::Mp4;
+use time::Duration;
+
+use crate::{
+    GOPRO_H264_HANDLER,
+    GOPRO_H265_HANDLER,
+    GOPRO_MIN_WIDTH_HEIGHT,
+    GpmfError,
+};
+use super::{FourCC, Stream, TimedSample, TimedSamples};
+
+/// Presentation time for each video frame,
+/// relative to video start.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameMap {
+    /// Start time for each frame,
+    /// including frame skips if set.
+    times: Vec<Duration>,
+    /// Duration of last frame.
+    last: Duration,
+    /// Time of first frame (from MP4 edit list),
+    /// added to all frame times.
+    pub time_first_frame: Duration,
+    /// `true` if the video is a low-resolution clip (LRV),
+    /// in which case `LSKP` rather than `MSKP` applies.
+    pub low_res: bool,
+}
+
+impl FrameMap {
+    /// Reads video frame timing from MP4-file.
+    ///
+    /// Note that this iterates over all video samples.
+    /// For repeated lookups keep the returned `FrameMap`
+    /// rather than using e.g. `Gpmf::frame_at()`.
+    pub fn new(path: &Path) -> Result<Self, GpmfError> {
+        let mut mp4 = Mp4::new(path)?;
+        Self::from_mp4(&mut mp4)
+    }
+
+    pub(crate) fn from_mp4(mp4: &mut Mp4) -> Result<Self, GpmfError> {
+        let time_first_frame = mp4.time_first_frame(true)?;
+        let low_res = mp4.resolution(true)? < GOPRO_MIN_WIDTH_HEIGHT;
+
+        // Video track handler depends on codec
+        let mut track = match mp4.track(GOPRO_H264_HANDLER, true) {
+            Ok(track) => track,
+            Err(_) => mp4.track(GOPRO_H265_HANDLER, true)?,
+        };
+
+        let mut times: Vec<Duration> = Vec::new();
+        let mut last = Duration::ZERO;
+        for result in track.samples() {
+            let sample = result?;
+            times.push(time_first_frame + sample.relative());
+            last = sample.duration();
+        }
+
+        if times.is_empty() {
+            return Err(GpmfError::NoSamples)
+        }
+
+        Ok(Self {
+            times,
+            last,
+            time_first_frame,
+            low_res,
+        })
+    }
+
+    /// Adds frame skips (`MSKP`, or `LSKP` for low-resolution clips)
+    /// to frame times. `devc_streams` must be the GPMF
+    /// for the same clip.
+    /// Frame times are returned as is if no frame skips are logged.
+    pub fn with_frame_skips(self, devc_streams: &[Stream]) -> Self {
+        let fourcc = match self.low_res {
+            true => FourCC::LSKP,
+            false => FourCC::MSKP,
+        };
+
+        // One value per encoded frame
+        let Some(skips) = TimedSamples::new(devc_streams, &fourcc).into_iter().next() else {
+            return self
+        };
+
+        let frame_duration = self.frame_duration();
+        let mut skipped: i64 = 0;
+        let times = self.times.iter()
+            .enumerate()
+            .map(|(i, t)| {
+                skipped += skips.samples.get(i)
+                    .and_then(|s| s.values.first())
+                    .map(|n| *n as i64)
+                    .unwrap_or(0);
+                *t + frame_duration * skipped as i32
+            })
+            .collect();
+
+        Self {
+            times,
+            ..self
+        }
+    }
+
+    /// Returns number of frames.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Returns `true` if there are no frames.
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Returns average frame duration.
+    pub fn frame_duration(&self) -> Duration {
+        match (self.times.first(), self.times.last()) {
+            (Some(first), Some(last)) if self.times.len() > 1 => {
+                (*last - *first) / (self.times.len() - 1) as i32
+            },
+            _ => self.last,
+        }
+    }
+
+    /// Returns average frame rate.
+    pub fn fps(&self) -> f64 {
+        1. / self.frame_duration().as_seconds_f64()
+    }
+
+    /// Returns zero-based frame number for
+    /// the frame displayed at `time`, relative to video start.
+    /// Returns `None` if `time` is before the first frame or
+    /// after the last frame.
+    pub fn frame_at(&self, time: Duration) -> Option<usize> {
+        let (first, last) = (self.times.first()?, self.times.last()?);
+        if time < *first || time >= *last + self.last {
+            return None
+        }
+        // Index of first frame starting after `time`
+        Some(self.times.partition_point(|t| *t <= time) - 1)
+    }
+
+    /// Returns start time for zero-based frame number `frame`,
+    /// relative to video start.
+    pub fn time_of_frame(&self, frame: usize) -> Option<Duration> {
+        self.times.get(frame).copied()
+    }
+
+    /// Returns start and end time for zero-based frame number `frame`,
+    /// relative to video start.
+    pub fn frame_span(&self, frame: usize) -> Option<(Duration, Duration)> {
+        let start = self.time_of_frame(frame)?;
+        let end = self.time_of_frame(frame + 1)
+            .unwrap_or(start + self.last);
+        Some((start, end))
+    }
+
+    /// Returns telemetry samples logged while
+    /// zero-based frame number `frame` is displayed.
+    pub fn samples<'a>(&self, frame: usize, timed: &'a TimedSamples) -> &'a [TimedSample] {
+        match self.frame_span(frame) {
+            Some((start, end)) => timed.between(start, end),
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::FrameMap;
+    use crate::{TimedSample, TimedSamples};
+    use crate::gpmf::Value;
+    use crate::tests::{container, timed, values};
+
+    fn ms(n: i64) -> Duration {
+        Duration::milliseconds(n)
+    }
+
+    /// Frame times from sample durations, as listed in `stts`.
+    fn frames(time_first_frame: Duration, durations: &[i64]) -> FrameMap {
+        let times = durations.iter()
+            .scan(time_first_frame, |t, d| {
+                let start = *t;
+                *t += ms(*d);
+                Some(start)
+            })
+            .collect();
+        FrameMap {
+            times,
+            last: durations.last().map(|d| ms(*d)).unwrap_or_default(),
+            time_first_frame,
+            low_res: false,
+        }
+    }
+
+    /// Frames at 100, 140, 180, 200, 260 ms, ending at 300 ms.
+    fn variable() -> FrameMap {
+        frames(ms(100), &[40, 40, 20, 60, 40])
+    }
+
+    #[test]
+    fn frame_at() {
+        let map = variable();
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.frame_at(ms(99)), None);
+        assert_eq!(map.frame_at(ms(100)), Some(0));
+        assert_eq!(map.frame_at(ms(139)), Some(0));
+        assert_eq!(map.frame_at(ms(140)), Some(1));
+        assert_eq!(map.frame_at(ms(199)), Some(2));
+        assert_eq!(map.frame_at(ms(200)), Some(3));
+        // Last frame
+        assert_eq!(map.frame_at(ms(260)), Some(4));
+        assert_eq!(map.frame_at(ms(299)), Some(4));
+        // Past the end
+        assert_eq!(map.frame_at(ms(300)), None);
+        assert_eq!(map.frame_at(Duration::hours(10)), None);
+        assert_eq!(FrameMap::default().frame_at(ms(0)), None);
+    }
+
+    #[test]
+    fn frame_times() {
+        let map = variable();
+
+        assert_eq!(map.time_of_frame(0), Some(ms(100)));
+        assert_eq!(map.time_of_frame(4), Some(ms(260)));
+        assert_eq!(map.time_of_frame(5), None);
+        assert_eq!(map.frame_span(0), Some((ms(100), ms(140))));
+        assert_eq!(map.frame_span(2), Some((ms(180), ms(200))));
+        assert_eq!(map.frame_span(4), Some((ms(260), ms(300))));
+        assert_eq!(map.frame_span(5), None);
+        assert_eq!(map.frame_duration(), ms(40));
+        assert_eq!(map.fps(), 25.);
+        // Single frame
+        assert_eq!(frames(ms(0), &[33]).frame_duration(), ms(33));
+    }
+
+    #[test]
+    fn frame_samples() {
+        let map = variable();
+        let samples = TimedSamples {
+            samples: (10..30)
+                .map(|i| TimedSample { time: ms(i * 10), values: vec![i as f64] })
+                .collect(),
+            ..TimedSamples::default()
+        };
+
+        let values = |frame: usize| -> Vec<f64> {
+            map.samples(frame, &samples).iter().map(|s| s.values[0]).collect()
+        };
+        assert_eq!(values(0), vec![10., 11., 12., 13.]);
+        assert_eq!(values(2), vec![18., 19.]);
+        assert_eq!(values(4), vec![26., 27., 28., 29.]);
+        assert!(values(5).is_empty());
+    }
+
+    #[test]
+    fn frame_skips() {
+        let map = frames(ms(0), &[40; 5]);
+        let mskp = values("MSKP", b'S', 2, [0, 1, 0, 2, 0].iter().map(|n| Value::Uint16(vec![*n])).collect());
+        let devc = timed(container("DEVC", vec![container("STRM", vec![mskp])]), 0., 0.2);
+
+        let map = map.with_frame_skips(&[devc]);
+        let times: Vec<_> = (0..5).filter_map(|i| map.time_of_frame(i)).collect();
+        assert_eq!(times, vec![ms(0), ms(80), ms(120), ms(240), ms(280)]);
+
+        // No skips logged
+        assert_eq!(frames(ms(0), &[40; 5]).with_frame_skips(&[]), frames(ms(0), &[40; 5]));
+    }
+}
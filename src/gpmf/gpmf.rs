@@ -28,17 +28,18 @@ use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
 use jpegiter::{Jpeg, JpegTag};
-use mp4iter::{Mp4, Mp4Error, Sample};
+use mp4iter::{Mp4, Sample};
 use rayon::iter::IntoParallelRefMutIterator;
 use rayon::prelude::{
     IntoParallelRefIterator,
     ParallelIterator,
 };
 use time::macros::datetime;
-use time::{Duration, PrimitiveDateTime};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
 
-use super::{FourCC, Stream, Timestamp};
+use super::{Diagnostic, FourCC, FrameMap, GpmfIter, Interpolation, ParseOptions, Resampled, Selection, Selector, Stream, TimeModel, TimedSamples, Timestamp, ValidationIssue, slice, validate};
 use crate::{
+    AltitudeReference,
     DataType,
     DeviceId,
     GoProPoint,
@@ -54,6 +55,7 @@ use crate::{
 
 /// Core GPMF struct.
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Gpmf {
     /// MP4 creation time. Only populated if source is an MP4 file.
     pub creation_time: Option<PrimitiveDateTime>,
@@ -115,6 +117,30 @@ impl Gpmf {
         }
     }
 
+    /// Extract and parse GPMF data from file, with parse options.
+    /// See `Gpmf::new()`.
+    ///
+    /// Returns `Diagnostic`s for recovered errors if
+    /// `ParseOptions::lenient` is set. JPEG-files are always
+    /// parsed in strict mode.
+    pub fn new_with_options(
+        path: &Path,
+        options: &ParseOptions
+    ) -> Result<(Self, Vec<Diagnostic>), GpmfError> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|s| s.to_lowercase())
+            .ok_or_else(|| GpmfError::InvalidFileType(path.to_owned()))?;
+
+        match ext.as_ref() {
+            "mp4" | "lrv" => Self::from_mp4_with_options(path, options),
+            "jpg" | "jpeg" => Ok((Self::from_jpg(path)?, Vec::new())),
+            // Possibly "raw" GPMF-file
+            _ => Self::from_raw_with_options(path, Some(50_000_000), options),
+        }
+    }
+
     /// Returns first DEVC stream as raw bytes.
     ///
     /// Presumed to be unique enough to use as a fingerprint
@@ -142,38 +168,108 @@ impl Gpmf {
     }
 
     pub fn from_mp4(path: &Path) -> Result<Self, GpmfError> {
+        Self::from_mp4_with_options(path, &ParseOptions::default())
+            .map(|(gpmf, _)| gpmf)
+    }
+
+    /// Extract and parse GPMF data from an unedited GoPro MP4-file,
+    /// with parse options.
+    ///
+    /// In lenient mode (`ParseOptions::lenient`), samples that can not be read
+    /// or parsed are skipped and corrupt data within samples is skipped or truncated.
+    /// Returns any such recoveries as `Diagnostic`s, with MP4 sample index set.
+    pub fn from_mp4_with_options(
+        path: &Path,
+        options: &ParseOptions
+    ) -> Result<(Self, Vec<Diagnostic>), GpmfError> {
         // Rust's BufReader deafult buffer size = 8192, slightly above
         // current GPMF sample size (8000 or slightly less).
         let mut mp4 = Mp4::new(path)?;
         let (creation_time, duration) = mp4.time(false)?;
         let mut track =  mp4.track(GOPRO_METADATA_HANDLER, false)?;
 
-        let mut samples: Vec<Sample> = track
-            .samples()
-            .collect::<Result<Vec<Sample>, Mp4Error>>()?;
-        let streams = samples
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let mut samples: Vec<(usize, Sample)> = Vec::new();
+        for (i, result) in track.samples().enumerate() {
+            match result {
+                Ok(sample) => samples.push((i, sample)),
+                // Skip, e.g. samples beyond the end of crash truncated clips
+                Err(err) if options.lenient => diagnostics.push(
+                    Diagnostic::new(0, None, format!("failed to read sample, skipping: {}", GpmfError::from(err)))
+                        .with_sample(i)
+                ),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let parsed = samples
             .par_iter_mut() // does this help at all when io is the bottleneck, esp on spinning disks?
-            .map(|sample| {
+            .map(|(i, sample)| {
                 let len = sample.len();
                 let ts = Timestamp::from(sample.deref());
-                let stream = Stream::new(sample, len)?
-                    .into_iter()
-                    .map(|s| s.with_time(&ts))
-                    .collect::<Vec<Stream>>();
-                Ok(stream)
+                // Absolute position of sample in MP4
+                let offset = sample.offset();
+                let mut diag: Vec<Diagnostic> = Vec::new();
+                let result = Stream::new_with_options(sample, len, options, &mut diag);
+                let mut diag = diag.into_iter()
+                    .map(|d| d.with_sample(*i))
+                    .collect::<Vec<_>>();
+                match result {
+                    Ok(streams) => {
+                        let streams = streams.into_iter()
+                            .map(|mut s| {
+                                s.offset_location(offset);
+                                s.with_time(&ts)
+                            })
+                            .collect::<Vec<Stream>>();
+                        Ok((streams, diag))
+                    },
+                    // Resync to next sample
+                    Err(err) if options.lenient => {
+                        diag.push(
+                            Diagnostic::new(0, None, format!("failed to parse sample, skipping: {err}"))
+                                .with_sample(*i)
+                        );
+                        Ok((Vec::new(), diag))
+                    },
+                    Err(err) => Err(err),
+                }
             })
-            .collect::<Result<Vec<Vec<Stream>>, GpmfError>>()?
-            // .into_par_iter()
-            .into_iter()
-            .flatten()
-            .collect::<Vec<Stream>>();
+            .collect::<Result<Vec<(Vec<Stream>, Vec<Diagnostic>)>, GpmfError>>()?;
 
-        Ok(Self {
+        let mut streams: Vec<Stream> = Vec::new();
+        for (strms, diag) in parsed.into_iter() {
+            streams.extend(strms);
+            diagnostics.extend(diag);
+        }
+
+        let gpmf = Self {
             creation_time: Some(creation_time),
             duration: Some(duration),
             streams,
             source: vec![path.to_owned()],
-        })
+        };
+
+        Ok((gpmf, diagnostics))
+    }
+
+    /// Returns an iterator over timestamped `DEVC` streams in a GoPro MP4,
+    /// parsed one MP4 sample at a time, as opposed to `Gpmf::from_mp4()`
+    /// which reads all samples into memory first.
+    /// Allows for processing long recordings in bounded memory,
+    /// or stopping early, e.g. after the first GPS lock.
+    ///
+    /// ```rs
+    /// use gpmf_rs::Gpmf;
+    /// use std::path::Path;
+    ///
+    /// for devc in Gpmf::stream_mp4(Path::new("GOPRO_VIDEO.MP4"))? {
+    ///     let devc = devc?;
+    ///     println!("{:?}", devc.time_relative());
+    /// }
+    /// ```
+    pub fn stream_mp4(path: &Path) -> Result<GpmfIter, GpmfError> {
+        GpmfIter::new(path)
     }
 
     /// Create GPMF from `Stream`s.
@@ -219,7 +315,9 @@ impl Gpmf {
         if let Some(mut app6) = segment {
             app6.seek(6); // seek past `GoPro\null`
             let len = app6.data.get_ref().len();
-            let stream = Stream::new(&mut app6.data, len)?;
+            let mut stream = Stream::new(&mut app6.data, len)?;
+            // Position of APP6 segment in JPEG not known
+            stream.iter_mut().for_each(|s| s.clear_location());
             return Ok(Self {
                 streams: stream,
                 source: vec![path.to_owned()],
@@ -237,6 +335,21 @@ impl Gpmf {
     /// Since the file is read into a memory buffer, `max_size` can be specified
     /// to avoid reading large files into memory.
     pub fn from_raw(path: &Path, max_size: Option<u64>) -> Result<Self, GpmfError> {
+        Self::from_raw_with_options(path, max_size, &ParseOptions::default())
+            .map(|(gpmf, _)| gpmf)
+    }
+
+    /// Returns GPMF from a "raw" GPMF-file, with parse options.
+    /// See `Gpmf::from_raw()`.
+    ///
+    /// In lenient mode (`ParseOptions::lenient`), parsing resyncs
+    /// to the next valid `DEVC` on corrupt data. Returns any such
+    /// recoveries as `Diagnostic`s.
+    pub fn from_raw_with_options(
+        path: &Path,
+        max_size: Option<u64>,
+        options: &ParseOptions
+    ) -> Result<(Self, Vec<Diagnostic>), GpmfError> {
         let file_size = path.metadata()?.len();
         if let Some(max) = max_size {
             if file_size > max {
@@ -250,13 +363,16 @@ impl Gpmf {
         let mut buf = vec![0_u8; file_size as usize];
         File::open(path)?.read_exact(&mut buf)?;
         let mut reader = Cursor::new(buf);
-        let streams = Stream::new(&mut reader, file_size as usize)?;
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let streams = Stream::new_with_options(&mut reader, file_size as usize, options, &mut diagnostics)?;
 
-        Ok(Self {
+        let gpmf = Self {
             streams,
             source: vec![path.to_owned()],
             ..Self::default()
-        })
+        };
+
+        Ok((gpmf, diagnostics))
     }
 
     /// GPMF from `Cursor<Vec<u8>>`.
@@ -269,6 +385,39 @@ impl Gpmf {
         })
     }
 
+    /// Encodes all streams as big endian GPMF bytes,
+    /// e.g. after editing values.
+    /// The result can be parsed with `Gpmf::from_cursor()`.
+    ///
+    /// Note that timestamps are not encoded, since these
+    /// are derived from MP4 timing.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GpmfError> {
+        Stream::encode(&self.streams)
+    }
+
+    /// Writes all streams as a "raw" GPMF-file,
+    /// that can be parsed with `Gpmf::from_raw()`.
+    /// Fails if `path` exists.
+    pub fn write_raw(&self, path: &Path) -> Result<(), GpmfError> {
+        let mut file = File::create_new(path)?;
+        Stream::write_all(&self.streams, &mut file)?;
+        Ok(())
+    }
+
+    /// Checks parsed GPMF for structural problems, such as
+    /// misaligned streams, container sizes that do not match their contents,
+    /// `TYPE` definitions that do not match the size of
+    /// complex data, `SCAL` values that do not match the sample width,
+    /// non-monotonic `TSMP` and `EMPT` counts.
+    ///
+    /// Intended for checking data from new devices or firmware.
+    /// Note that alignment and container size checks rely on byte locations
+    /// (`Stream::location`), and corrupt data is only retained
+    /// in lenient mode (see `ParseOptions`).
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        validate::validate(&self.streams)
+    }
+
     pub fn print(&self) {
         self.iter()
             .enumerate()
@@ -316,6 +465,15 @@ impl Gpmf {
         None
     }
 
+    /// Returns streams matching a selector query,
+    /// together with inherited timestamp and
+    /// `SCAL`, `SIUN`, `ORIN` metadata. E.g.
+    /// `DEVC[DVNM='Hero11 Black']/STRM[STNM~'Gyro']/GYRO`.
+    /// See `gpmf::select` for syntax.
+    pub fn select(&self, selector: &str) -> Result<Vec<Selection<'_>>, GpmfError> {
+        Ok(Selector::new(selector)?.select(&self.streams))
+    }
+
     /// Append `Stream`s to `self.streams`.
     pub fn append(&mut self, streams: &mut Vec<Stream>) {
         self.streams.append(streams)
@@ -504,6 +662,37 @@ impl Gpmf {
             .collect::<Vec<_>>())
     }
 
+    /// For `GPS5` models, Hero10 and earlier.
+    /// Returns every logged point, rather than one average
+    /// point per `STRM` as for `Gpmf::gps5()`.
+    ///
+    /// Point timestamps are derived from `TSMP` if logged
+    /// (see `Gpmf::timed_samples()`), otherwise spread evenly
+    /// across the `DEVC` duration. Datetime is derived from
+    /// `GPSU` for each cluster plus the offset within the cluster.
+    /// Satellite lock (`GPSF`) and DOP (`GPSP`) are inherited from
+    /// the cluster.
+    pub fn gps5_expanded(&self) -> Gps {
+        // Camera is the first device
+        let timed = self.timed_samples(&FourCC::GPS5).into_iter().next();
+
+        // Each cluster is paired with the timed samples
+        // derived from the same DEVC
+        let points = self.streams.iter()
+            .enumerate()
+            .flat_map(|(i, devc)| devc.filter(&DataType::Gps5)
+                .into_iter()
+                .map(move |s| (i, s)))
+            .filter_map(|(i, s)| GoProPoint::from_gps5_timed(
+                &s,
+                timed.as_ref().and_then(|t| t.payload(i))
+            ))
+            .flatten()
+            .collect::<Vec<_>>();
+
+        Gps::new(points)
+    }
+
     /// For `GPS9` models, Hero11 and later.
     ///
     /// Since the newer `GPS9` format logs datetime,
@@ -518,9 +707,168 @@ impl Gpmf {
             .collect::<Vec<_>>())
     }
 
+    /// Returns vertical datum for GPS altitude.
+    /// `GPSA` (GPS altitude) is logged as `MSLV` (mean sea level)
+    /// by Hero9 and later (and Hero8 with recent firmware).
+    /// Earlier devices log altitude above the WGS84 ellipsoid.
+    pub fn altitude_reference(&self) -> AltitudeReference {
+        let gpsa: Option<String> = self.find(&FourCC::GPSA)
+            .and_then(|s| s.first_value())
+            .and_then(|v| v.into());
+        match gpsa.as_deref().map(|s| s.trim()) {
+            Some("MSLV") => AltitudeReference::MeanSeaLevel,
+            _ => AltitudeReference::Ellipsoid,
+        }
+    }
+
+    /// Replays GPS log as NMEA 0183 sentences to `writer`,
+    /// e.g. a `TcpStream` as a local replay source.
+    /// See `Gps::replay_nmea()`.
+    pub fn replay_nmea<W: std::io::Write>(&self, writer: &mut W, speed: f64) -> Result<(), GpmfError> {
+        self.gps().replay_nmea(writer, speed)
+    }
+
+    /// Returns data streams with specified Four CC, e.g. `GYRO`,
+    /// with a timestamp for each individual sample,
+    /// one `TimedSamples` per device.
+    /// See `TimedSamples` for how timestamps are derived.
+    ///
+    /// Requires that `DEVC` timestamps are set,
+    /// i.e. empty for raw GPMF sources.
+    pub fn timed_samples(&self, fourcc: &FourCC) -> Vec<TimedSamples> {
+        TimedSamples::new(&self.streams, fourcc)
+    }
+
+    /// Resamples specified data types onto a common time line
+    /// at `rate` Hz, e.g. for a single, synchronized table
+    /// of accelerometer, gyroscope and GPS data.
+    /// Rows cover the time span where all data types have samples.
+    /// See `Resampled`.
+    ///
+    /// Requires that `DEVC` timestamps are set,
+    /// i.e. not supported for raw GPMF sources.
+    pub fn resample(
+        &self,
+        data_types: &[DataType],
+        rate: f64,
+        method: Interpolation
+    ) -> Result<Resampled, GpmfError> {
+        Resampled::new(&self.streams, data_types, rate, method)
+    }
+
+    /// Returns video frame timing for the MP4 source,
+    /// including frame skips (`MSKP`/`LSKP`) if logged.
+    /// For merged GPMF from multiple clips, the first clip is used.
+    /// See `FrameMap`.
+    ///
+    /// Note that this reads all video samples from disk.
+    pub fn frames(&self) -> Result<FrameMap, GpmfError> {
+        let path = self.source.first().ok_or(GpmfError::PathNotSet)?;
+        Ok(FrameMap::new(path)?.with_frame_skips(&self.streams))
+    }
+
+    /// Returns zero-based frame number for
+    /// the frame displayed at `time`, relative to video start.
+    /// Reads video timing on each call,
+    /// use `Gpmf::frames()` for repeated lookups.
+    pub fn frame_at(&self, time: Duration) -> Result<Option<usize>, GpmfError> {
+        Ok(self.frames()?.frame_at(time))
+    }
+
+    /// Returns start time for zero-based frame number `frame`,
+    /// relative to video start.
+    /// Reads video timing on each call,
+    /// use `Gpmf::frames()` for repeated lookups.
+    pub fn time_of_frame(&self, frame: usize) -> Result<Option<Duration>, GpmfError> {
+        Ok(self.frames()?.time_of_frame(frame))
+    }
+
+    /// Returns `DEVC` streams within the time range `start` (inclusive)
+    /// to `end` (exclusive), relative to video start.
+    /// `DEVC` streams that partially overlap the range
+    /// are split at sample boundaries, with `TSMP`, `STMP` and `GPSU`
+    /// adjusted accordingly. Timestamps remain relative to video start.
+    /// Byte locations are removed for streams that were modified.
+    ///
+    /// `DEVC` streams without timestamps (e.g. raw GPMF)
+    /// are excluded.
+    pub fn slice(&self, start: Duration, end: Duration) -> Self {
+        let streams: Vec<Stream> = self.streams.iter()
+            .filter_map(|devc| slice::slice_devc(devc, start, end))
+            .collect();
+        Self {
+            duration: Some(streams.iter()
+                .map(|s| s.time_duration().unwrap_or(Duration::ZERO))
+                .sum()),
+            streams,
+            creation_time: self.creation_time,
+            source: self.source.to_owned(),
+        }
+    }
+
+    /// Returns `DEVC` streams within the absolute time range `start` (inclusive)
+    /// to `end` (exclusive). UTC is converted to time relative
+    /// to video start via `Gpmf::time_model()`. See `Gpmf::slice()`.
+    pub fn slice_utc(&self, start: OffsetDateTime, end: OffsetDateTime) -> Result<Self, GpmfError> {
+        let model = self.time_model()?;
+        Ok(self.slice(model.relative(start), model.relative(end)))
+    }
+
+    /// Returns mapping from time relative to video start
+    /// to absolute UTC time, fitted to GPS points
+    /// with at least a 2D satellite lock.
+    /// Falls back to MP4 creation time if there are no such points.
+    /// See `TimeModel`.
+    pub fn time_model(&self) -> Result<TimeModel, GpmfError> {
+        TimeModel::new(&self.gps(), None, self.creation_time)
+    }
+
     /// Sensor data. Note that sensors depend on model.
     /// All (?) GoPro cameras contain gyroscope and accelerometer.
     pub fn imu(&self, sensor_type: &ImuType) -> Vec<Imu> {
         Imu::from_gpmf(self, sensor_type)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::Gpmf;
+    use crate::tests::{container, gps5_strm, timed};
+
+    #[test]
+    fn gps5_expanded_tsmp() {
+        let samples = || vec![vec![0, 0, 0, 0, 0], vec![10, 10, 0, 0, 0]];
+        // Payloads are shorter than the DEVC interval, so TSMP timing
+        // (2 Hz) differs from spreading samples across DEVC duration.
+        let streams = vec![
+            timed(container("DEVC", vec![gps5_strm(Some("230101120000.000"), Some(2), samples())]), 0., 0.8),
+            // Missing GPSU, no points for this cluster
+            timed(container("DEVC", vec![gps5_strm(None, Some(4), samples())]), 1., 0.8),
+            timed(container("DEVC", vec![gps5_strm(Some("230101120002.000"), Some(6), samples())]), 2., 0.8),
+        ];
+        let gpmf = Gpmf {
+            creation_time: None,
+            duration: None,
+            streams,
+            source: Vec::new(),
+        };
+
+        let gps = gpmf.gps5_expanded();
+
+        assert_eq!(
+            gps.iter().map(|p| p.timestamp.as_seconds_f64()).collect::<Vec<_>>(),
+            vec![0., 0.5, 2., 2.5]
+        );
+        assert_eq!(
+            gps.iter().map(|p| p.datetime().unwrap()).collect::<Vec<_>>(),
+            vec![
+                datetime!(2023-01-01 12:00:00 UTC),
+                datetime!(2023-01-01 12:00:00.5 UTC),
+                datetime!(2023-01-01 12:00:02 UTC),
+                datetime!(2023-01-01 12:00:02.5 UTC),
+            ]
+        );
+    }
+}
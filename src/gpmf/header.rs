@@ -1,6 +1,6 @@
 //! GPMF header that precedes each GPMF stream.
 
-use std::{io::{BufRead, Seek, Read}, fmt};
+use std::{io::{BufRead, Seek, Read, Write}, fmt};
 
 use binrw::BinReaderExt;
 
@@ -9,6 +9,7 @@ use crate::GpmfError;
 
 /// GPMF header.
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     /// FourCC.
     pub fourcc: FourCC,
@@ -52,7 +53,8 @@ impl Header {
     /// to allow Type and structure size to switch places so that all string data
     /// becomes structure size X (byte length of utf-8 string), repeat 1, e.g. for older devices:
     /// `['G'] ['y'] ['r'] ['o']`, will instead be parsed into `['G', 'y', 'r', 'o']` -> String "Gyro".
-    /// This is also true for Huffman encoded data loads, but these are currently not supported.
+    /// Huffman encoded data loads (`#`) are decoded into their uncompressed form
+    /// when parsed into a `Stream`.
     pub fn new<R: Read + BufRead + Seek>(reader: &mut R) -> Result<Self, GpmfError> {
         let fourcc = FourCC::new(reader)?;
 
@@ -69,29 +71,66 @@ impl Header {
         let tmp_basesize: u8 = reader.read_ne()?;
         let tmp_repeats: u16 = reader.read_be()?;
 
+        Ok(Self::from_parts(fourcc, basetype, tmp_basesize, tmp_repeats))
+    }
+
+    /// Creates header from its raw components, i.e. the values
+    /// following the FourCC: `<type><size><repeat>`.
+    /// Also used for the inner header of Huffman compressed
+    /// data loads (`#`), which lacks a FourCC.
+    pub(crate) fn from_parts(fourcc: FourCC, basetype: u8, basesize: u8, repeats: u16) -> Self {
         // Check if structure size = 1 and basesize is a char,
         // and switch places if so to simplify string parsing.
-        let (basesize, repeats) = match (basetype, tmp_basesize) {
-            (b'c', 1) => (tmp_repeats, tmp_basesize as u16),
-            _ => (tmp_basesize as u16, tmp_repeats)
+        let (basesize, repeats) = match (basetype, basesize) {
+            (b'c', 1) => (repeats, basesize as u16),
+            _ => (basesize as u16, repeats)
         };
 
         // Set padding value for 32-bit alignment (0-3)
-        let mut pad = 0;
-        loop {
-            match (basesize * repeats + pad as u16) % 4 {
-                0 => break,
-                _ => pad += 1,
-            }
-        }
+        let pad = ((4 - (basesize as u32 * repeats as u32) % 4) % 4) as u8;
 
-        Ok(Self {
+        Self {
             fourcc,
             basetype,
             basesize,
             repeats,
             pad,
-        })
+        }
+    }
+
+    /// Writes header as big endian GPMF bytes.
+    ///
+    /// Strings that were parsed with structure size and repeat
+    /// switched (see `Header::new()`) are written with
+    /// structure size 1 if their length does not fit in a `u8`.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), GpmfError> {
+        let (basesize, repeats) = match (self.basetype, self.repeats, u8::try_from(self.basesize)) {
+            (_, _, Ok(size)) => (size, self.repeats),
+            (b'c', 1, Err(_)) => (1, self.basesize),
+            _ => return Err(GpmfError::EncodeError(
+                format!("{} structure size {} exceeds {}", self.fourcc.to_str(), self.basesize, u8::MAX)
+            )),
+        };
+
+        writer.write_all(&self.fourcc.to_bytes())?;
+        writer.write_all(&[self.basetype, basesize])?;
+        writer.write_all(&repeats.to_be_bytes())?;
+
+        Ok(())
+    }
+
+    /// Returns header for a container (`Header::basetype = 0`)
+    /// wrapping `len` bytes of GPMF data.
+    pub(crate) fn nested(fourcc: &FourCC, len: usize) -> Result<Self, GpmfError> {
+        // Containers are structure size 1 with byte length as repeat,
+        // unless the byte length exceeds u16::MAX.
+        // Contained streams are 32-bit aligned so
+        // structure size 4 is always valid.
+        let (basesize, repeats) = match u16::try_from(len) {
+            Ok(rpt) => (1, rpt),
+            Err(_) => (4, u16::try_from(len / 4)?),
+        };
+        Ok(Self::from_parts(fourcc.to_owned(), 0, basesize, repeats))
     }
 
     /// Converts header to single-value header with specified type.
@@ -110,9 +149,29 @@ impl Header {
         self.fourcc == FourCC::Invalid
     }
 
+    /// Returns `true` if basetype is defined
+    /// in the GPMF specification.
+    pub fn has_known_basetype(&self) -> bool {
+        matches!(self.basetype,
+            0 | b'b' | b'B' | b'c' | b'd' | b'f' | b'F' | b'G' | b'j' | b'J'
+            | b'l' | b'L' | b'q' | b'Q' | b's' | b'S' | b'U' | b'u' | b'?' | b'#'
+        )
+    }
+
+    /// Returns `true` if header is plausible, i.e. Four CC
+    /// consists of ASCII alphanumerics or spaces and
+    /// the basetype is known.
+    /// Used for detecting corrupt data.
+    pub fn is_valid(&self) -> bool {
+        self.fourcc.to_bytes()
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || *b == b' ')
+        && self.has_known_basetype()
+    }
+
     /// Get base length (same as `std::mem::size_of::<T>()`).
-    /// Only used for COMPLEX BaseTypes.
-    const fn baselen(basetype: &u8) -> u8 {
+    /// Used for COMPLEX BaseTypes and Huffman compressed data loads.
+    pub(crate) const fn baselen(basetype: &u8) -> u8 {
         match basetype {
             // Value::Sint8, Uint8, Ascii
             b'b' | b'B' | b'c' | 0 | b'?' => 1,
@@ -0,0 +1,450 @@
+//! Decoder for Huffman compressed GPMF data loads (basetype `#`/35).
+//!
+//! Newer devices may compress high frequency sensor streams, such as `ACCL` and `GYRO`.
+//! A compressed stream is laid out as:
+//! ```text
+//! 4-CC '#'<new size><new repeat> <type><size><repeat> <compressed data ...>
+//! ```
+//! where `<type><size><repeat>` is the header for the uncompressed data.
+//!
+//! The compressed data contains one block per channel (e.g. x, y, z for `GYRO`):
+//! - 16-bit quantization value
+//! - first sample, uncompressed
+//! - Huffman coded deltas for the remaining samples, terminated by an end code,
+//!   padded to 16-bit alignment.
+//!
+//! See `GPMF_Decompress()` and `GPMF_bitstream.h` at <https://github.com/gopro/gpmf-parser>.
+
+use std::io::{BufRead, Cursor, Read, Seek};
+
+use super::{Header, Value};
+use crate::GpmfError;
+
+/// Decoded Huffman code word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Code {
+    /// Delta magnitude. Non-zero values are followed by a sign bit.
+    Delta(u16),
+    /// Run of zero deltas.
+    Zeros(u16),
+    /// Escape code, followed by an uncoded delta
+    /// with the same bit size as the base type.
+    Escape,
+    /// Ends the compressed data for the current channel.
+    /// Remaining samples repeat the last value.
+    End,
+}
+
+/// Code book as `(bit length, code word, code)`.
+/// Mirrors the tables in gpmf-parser's `GPMF_bitstream.h`.
+const CODEBOOK: &[(u8, u16, Code)] = &[
+    // Deltas
+    (1, 0b0, Code::Delta(0)),
+    (2, 0b10, Code::Delta(1)),
+    (4, 0b1100, Code::Delta(2)),
+    (5, 0b11011, Code::Delta(3)),
+    (5, 0b11101, Code::Delta(4)),
+    (6, 0b110100, Code::Delta(5)),
+    (6, 0b110101, Code::Delta(6)),
+    (6, 0b111110, Code::Delta(7)),
+    (7, 0b1110000, Code::Delta(8)),
+    (7, 0b1110011, Code::Delta(9)),
+    (7, 0b1111000, Code::Delta(10)),
+    (7, 0b1111001, Code::Delta(11)),
+    (7, 0b1111011, Code::Delta(12)),
+    (8, 0b11100100, Code::Delta(13)),
+    (8, 0b11100101, Code::Delta(14)),
+    (8, 0b11111110, Code::Delta(15)),
+    (9, 0b111000100, Code::Delta(16)),
+    // Zero runs
+    (7, 0b1111010, Code::Zeros(16)),
+    (8, 0b11111100, Code::Zeros(32)),
+    (8, 0b11111101, Code::Zeros(64)),
+    (8, 0b11111111, Code::Zeros(128)),
+    // Control codes
+    (16, 0b1110001010000000, Code::Escape),
+    (16, 0b1110001010000001, Code::End),
+];
+
+/// Max code word length in bits.
+const CODE_MAX_LEN: u8 = 16;
+
+/// Reads bits MSB first from a byte slice.
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32, GpmfError> {
+        let mut bits = 0_u32;
+        for _ in 0..count {
+            let byte = self.data
+                .get(self.pos / 8)
+                .ok_or_else(|| decode_error("unexpected end of compressed data"))?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            bits = (bits << 1) | bit as u32;
+            self.pos += 1;
+        }
+        Ok(bits)
+    }
+
+    /// Reads bits until a code word in `CODEBOOK` is matched.
+    fn read_code(&mut self) -> Result<Code, GpmfError> {
+        let mut word = 0_u16;
+        for len in 1..=CODE_MAX_LEN {
+            word = (word << 1) | self.read_bits(1)? as u16;
+            if let Some((.., code)) = CODEBOOK.iter().find(|(l, w, _)| *l == len && *w == word) {
+                return Ok(*code)
+            }
+        }
+        Err(decode_error("invalid code word"))
+    }
+
+    /// Number of bytes read so far, including
+    /// padding to 16-bit alignment.
+    fn aligned_len(&self) -> usize {
+        self.pos.div_ceil(16) * 2
+    }
+}
+
+fn decode_error(msg: &str) -> GpmfError {
+    GpmfError::DecompressError(msg.to_owned())
+}
+
+/// Returns `N` bytes from `bytes`, starting at `pos`.
+fn array<const N: usize>(bytes: &[u8], pos: usize) -> Result<[u8; N], GpmfError> {
+    bytes.get(pos..pos + N)
+        .and_then(|s| s.try_into().ok())
+        .ok_or_else(|| decode_error("unexpected end of compressed data"))
+}
+
+/// Reads a single uncompressed value of the specified basetype.
+fn read_raw(bytes: &[u8], pos: usize, basetype: u8) -> Result<i64, GpmfError> {
+    let value = match basetype {
+        b'b' => i64::from(i8::from_be_bytes(array(bytes, pos)?)),
+        b'B' => i64::from(u8::from_be_bytes(array(bytes, pos)?)),
+        b's' => i64::from(i16::from_be_bytes(array(bytes, pos)?)),
+        b'S' => i64::from(u16::from_be_bytes(array(bytes, pos)?)),
+        b'l' => i64::from(i32::from_be_bytes(array(bytes, pos)?)),
+        b'L' => i64::from(u32::from_be_bytes(array(bytes, pos)?)),
+        b => return Err(GpmfError::UnknownBaseType(b)),
+    };
+    Ok(value)
+}
+
+/// Writes a single value as big endian bytes of the specified basetype.
+fn write_raw(buf: &mut Vec<u8>, value: i64, basetype: u8) {
+    match basetype {
+        b'b' => buf.extend((value as i8).to_be_bytes()),
+        b'B' => buf.extend((value as u8).to_be_bytes()),
+        b's' => buf.extend((value as i16).to_be_bytes()),
+        b'S' => buf.extend((value as u16).to_be_bytes()),
+        b'l' => buf.extend((value as i32).to_be_bytes()),
+        b'L' => buf.extend((value as u32).to_be_bytes()),
+        _ => (),
+    }
+}
+
+fn set(data: &mut [i64], index: usize, value: i64) -> Result<(), GpmfError> {
+    *data.get_mut(index)
+        .ok_or_else(|| decode_error("decoded samples exceed uncompressed repeat count"))? = value;
+    Ok(())
+}
+
+/// Decodes a compressed stream (`Header::basetype` = `#`) from `reader`
+/// and returns the header for the uncompressed data together with
+/// the decoded `Value`s, i.e. the same result as if the stream had never been compressed.
+///
+/// The reader must be positioned directly after the `#` header.
+/// Padding is not consumed.
+///
+/// `max_size` is the max size in bytes for the uncompressed data.
+pub(crate) fn decompress<R: Read + BufRead + Seek>(
+    reader: &mut R,
+    header: &Header,
+    complextype: Option<&str>,
+    max_size: usize,
+) -> Result<(Header, Vec<Value>), GpmfError> {
+    let mut buf = vec![0_u8; header.size(false) as usize];
+    reader.read_exact(&mut buf)?;
+
+    // Header for uncompressed data: <type><size><repeat>
+    let [basetype, basesize, rpt0, rpt1] = array::<4>(&buf, 0)?;
+    let inner = Header::from_parts(
+        header.fourcc.to_owned(),
+        basetype,
+        basesize,
+        u16::from_be_bytes([rpt0, rpt1])
+    );
+
+    // Uncompressed size is untrusted input
+    let size = inner.size(false) as usize;
+    if size > max_size {
+        return Err(GpmfError::MaxKlvSizeExceeded{max: max_size, got: size})
+    }
+
+    let typesize = Header::baselen(&inner.basetype) as usize;
+    let channels = inner.basesize as usize / typesize;
+    let samples = inner.repeats as usize;
+
+    if channels * samples == 0 {
+        return Ok((inner, vec![Value::Empty]))
+    }
+
+    // Channel values for all samples, sample by sample, i.e. `[x0, y0, z0, x1, y1, z1, ...]`
+    let mut data = vec![0_i64; channels * samples];
+
+    let mut pos = 4;
+    for channel in 0..channels {
+        let quant = i64::from(u16::from_be_bytes(array(&buf, pos)?));
+        pos += 2;
+
+        let mut value = read_raw(&buf, pos, inner.basetype)?;
+        set(&mut data, channel, value)?;
+        pos += typesize;
+
+        let mut bits = BitReader::new(buf.get(pos..).unwrap_or_default());
+        let mut sample = 1;
+        loop {
+            match bits.read_code()? {
+                Code::Delta(magnitude) => {
+                    let mut delta = i64::from(magnitude);
+                    // sign bit, 1 = negative
+                    if magnitude != 0 && bits.read_bits(1)? == 1 {
+                        delta = -delta;
+                    }
+                    value += delta * quant;
+                    set(&mut data, sample * channels + channel, value)?;
+                    sample += 1;
+                },
+                Code::Zeros(count) => {
+                    for _ in 0..count {
+                        set(&mut data, sample * channels + channel, value)?;
+                        sample += 1;
+                    }
+                },
+                Code::Escape => {
+                    let bitsize = (typesize * 8) as u8;
+                    let raw = i64::from(bits.read_bits(bitsize)?);
+                    // sign extend uncoded delta
+                    let delta = match raw & (1 << (bitsize - 1)) {
+                        0 => raw,
+                        _ => raw - (1 << bitsize),
+                    };
+                    value += delta * quant;
+                    set(&mut data, sample * channels + channel, value)?;
+                    sample += 1;
+                },
+                Code::End => {
+                    while sample < samples {
+                        set(&mut data, sample * channels + channel, value)?;
+                        sample += 1;
+                    }
+                    break
+                },
+            }
+        }
+
+        pos += bits.aligned_len();
+    }
+
+    // Re-create the uncompressed data load and parse it
+    // as any other stream.
+    let mut raw: Vec<u8> = Vec::with_capacity(inner.size(false) as usize);
+    for value in data.into_iter() {
+        write_raw(&mut raw, value, inner.basetype);
+    }
+
+    let mut cursor = Cursor::new(raw);
+    let values = (0..inner.repeats)
+        .map(|_| Value::new(&mut cursor, &inner, complextype))
+        .collect::<Result<Vec<Value>, GpmfError>>()?;
+
+    Ok((inner, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{CODEBOOK, Code, decompress};
+    use crate::gpmf::{FourCC, Header, Stream, StreamType, Value};
+
+    /// Writes bits MSB first, the inverse of `BitReader`.
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        pos: usize,
+    }
+
+    impl BitWriter {
+        fn bits(mut self, count: u8, bits: u32) -> Self {
+            for i in (0..count).rev() {
+                if self.pos % 8 == 0 {
+                    self.data.push(0);
+                }
+                let bit = ((bits >> i) & 1) as u8;
+                *self.data.last_mut().unwrap() |= bit << (7 - self.pos % 8);
+                self.pos += 1;
+            }
+            self
+        }
+
+        fn code(self, code: Code) -> Self {
+            let (len, word, _) = CODEBOOK.iter()
+                .find(|(.., c)| *c == code)
+                .unwrap();
+            self.bits(*len, *word as u32)
+        }
+
+        /// Delta code followed by sign bit.
+        fn delta(self, delta: i16) -> Self {
+            let writer = self.code(Code::Delta(delta.unsigned_abs()));
+            match delta {
+                0 => writer,
+                _ => writer.bits(1, (delta < 0) as u32),
+            }
+        }
+
+        /// Returns bytes padded to 16-bit alignment.
+        fn finish(mut self) -> Vec<u8> {
+            self.data.resize(self.pos.div_ceil(16) * 2, 0);
+            self.data
+        }
+    }
+
+    /// Compressed `i16` channel: quantization, first value, bitstream.
+    fn channel(quant: u16, first: i16, bits: BitWriter) -> Vec<u8> {
+        let mut buf = quant.to_be_bytes().to_vec();
+        buf.extend(first.to_be_bytes());
+        buf.extend(bits.finish());
+        buf
+    }
+
+    /// Compressed `i16` data load with `channels` and `repeats`.
+    fn payload(channels: &[Vec<u8>], repeats: u16) -> Vec<u8> {
+        let mut buf = vec![b's', 2 * channels.len() as u8];
+        buf.extend(repeats.to_be_bytes());
+        channels.iter().for_each(|c| buf.extend(c));
+        buf
+    }
+
+    fn compressed_header(payload: &[u8]) -> Header {
+        Header::from_parts(FourCC::GYRO, b'#', 1, payload.len() as u16)
+    }
+
+    fn sint16(values: &[i16]) -> Vec<Value> {
+        values.iter().map(|v| Value::Sint16(vec![*v])).collect()
+    }
+
+    #[test]
+    fn decode_codes() {
+        let bits = BitWriter::default()
+            .delta(1)
+            .delta(-2)
+            .delta(0)
+            .code(Code::Zeros(16))
+            .code(Code::Escape)
+            .bits(16, (-10_i16) as u16 as u32)
+            .code(Code::End);
+        let data = payload(&[channel(2, 100, bits)], 24);
+
+        let (header, values) = decompress(&mut Cursor::new(&data), &compressed_header(&data), None, usize::MAX).unwrap();
+
+        let mut expected = vec![100, 102, 98, 98];
+        expected.extend([98; 16]);
+        // Escaped delta -10 * quantization 2, then repeated by end code
+        expected.extend([78; 4]);
+        assert_eq!(header, Header::from_parts(FourCC::GYRO, b's', 2, 24));
+        assert_eq!(values, sint16(&expected));
+    }
+
+    /// Byte literal encoded by hand from the code words in gpmf-parser's
+    /// `GPMF_bitstream.h`, independent of `CODEBOOK` and `BitWriter`.
+    #[test]
+    fn decode_literal() {
+        let data: &[u8] = &[
+            // Uncompressed header: 's', 2 channels x 2 bytes, 8 repeats
+            0x73, 0x04, 0x00, 0x08,
+            // Channel 1: quantization 1, first value 100
+            0x00, 0x01, 0x00, 0x64,
+            // 10 0 | 1100 1 | 0 | 11011 0 | 1110001010000001 | pad
+            // +1, -2, 0, +3, end
+            0x99, 0x6D, 0xC5, 0x02,
+            // Channel 2: quantization 10, first value -5
+            0x00, 0x0A, 0xFF, 0xFB,
+            // 10 0 | 1110001010000001 | pad
+            // +1, end
+            0x9C, 0x50, 0x20, 0x00,
+        ];
+
+        let (header, values) = decompress(&mut Cursor::new(data), &compressed_header(data), None, usize::MAX).unwrap();
+
+        assert_eq!(header, Header::from_parts(FourCC::GYRO, b's', 4, 8));
+        assert_eq!(values, vec![
+            Value::Sint16(vec![100, -5]),
+            Value::Sint16(vec![101, 5]),
+            Value::Sint16(vec![99, 5]),
+            Value::Sint16(vec![99, 5]),
+            Value::Sint16(vec![102, 5]),
+            Value::Sint16(vec![102, 5]),
+            Value::Sint16(vec![102, 5]),
+            Value::Sint16(vec![102, 5]),
+        ]);
+    }
+
+    #[test]
+    fn decode_channels() {
+        let x = BitWriter::default().delta(1).delta(1).delta(1).code(Code::End);
+        let y = BitWriter::default().code(Code::End);
+        let z = BitWriter::default().delta(-2).code(Code::End);
+        let data = payload(&[channel(1, 1, x), channel(10, -5, y), channel(1, 1000, z)], 4);
+
+        let (header, values) = decompress(&mut Cursor::new(&data), &compressed_header(&data), None, usize::MAX).unwrap();
+
+        assert_eq!(header, Header::from_parts(FourCC::GYRO, b's', 6, 4));
+        assert_eq!(values, vec![
+            Value::Sint16(vec![1, -5, 1000]),
+            Value::Sint16(vec![2, -5, 998]),
+            Value::Sint16(vec![3, -5, 998]),
+            Value::Sint16(vec![4, -5, 998]),
+        ]);
+    }
+
+    #[test]
+    fn decode_exceeding_repeats() {
+        let bits = BitWriter::default().code(Code::Zeros(16)).code(Code::End);
+        let data = payload(&[channel(1, 0, bits)], 4);
+
+        assert!(decompress(&mut Cursor::new(&data), &compressed_header(&data), None, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn decode_stream() {
+        let x = BitWriter::default().delta(1).code(Code::End);
+        let y = BitWriter::default().delta(-1).code(Code::End);
+        let data = payload(&[channel(1, 0, x), channel(1, 0, y)], 3);
+
+        let header = compressed_header(&data);
+        let mut klv = Vec::new();
+        header.write(&mut klv).unwrap();
+        klv.extend(&data);
+        klv.extend(vec![0; header.pad as usize]);
+
+        let streams = Stream::new(&mut Cursor::new(&klv), klv.len()).unwrap();
+
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].header, Header::from_parts(FourCC::GYRO, b's', 4, 3));
+        assert_eq!(streams[0].streams, StreamType::Values(vec![
+            Value::Sint16(vec![0, 0]),
+            Value::Sint16(vec![1, -1]),
+            Value::Sint16(vec![1, -1]),
+        ]));
+    }
+}
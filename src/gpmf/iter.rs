@@ -0,0 +1,127 @@
+//! Streaming, per-sample parse of the GoPro MP4 GPMF track.
+//!
+//! `Gpmf::from_mp4()` reads all samples into memory before parsing.
+//! `GpmfIter` instead reads and parses one MP4 sample at a time,
+//! returning timestamped `DEVC` streams, so that long recordings
+//! can be processed in bounded memory, and iteration stopped early.
+//!
+//! ```rs
+//! use gpmf_rs::{FourCC, Gpmf};
+//! use std::path::Path;
+//!
+//! // Find the first DEVC with a GPS lock, then stop reading.
+//! let devc = Gpmf::stream_mp4(Path::new("GOPRO_VIDEO.MP4"))?
+//!     .filter_map(|result| result.ok())
+//!     .find(|devc| devc.find_all(&FourCC::STRM).iter()
+//!         .filter_map(|s| s.find(&FourCC::GPSF).and_then(|f| f.to_u32()))
+//!         .any(|fix| fix.first() >= Some(&2))
+//!     );
+//! ```
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+
+use mp4iter::Mp4;
+use time::{Duration, PrimitiveDateTime};
+
+use super::{Stream, Timestamp};
+use crate::{GpmfError, GOPRO_METADATA_HANDLER};
+
+/// Max number of parsed MP4 samples
+/// buffered ahead of iteration.
+const SAMPLE_BUFFER: usize = 4;
+
+/// Iterator over timestamped `DEVC` streams in a GoPro MP4,
+/// parsed one MP4 sample at a time.
+///
+/// Reading and parsing is done in a separate thread,
+/// at most `SAMPLE_BUFFER` samples ahead. Dropping the iterator
+/// stops reading.
+///
+/// Iteration ends after the first error.
+#[derive(Debug)]
+pub struct GpmfIter {
+    /// MP4 creation time.
+    pub creation_time: PrimitiveDateTime,
+    /// MP4 duration.
+    pub duration: Duration,
+    /// Path to the GoPro MP4 source.
+    pub source: PathBuf,
+    /// Parsed samples, one `Vec<Stream>` per MP4 sample.
+    receiver: Receiver<Result<Vec<Stream>, GpmfError>>,
+    /// Streams in current sample not yet returned.
+    /// Some devices log more than one `DEVC` per sample.
+    buffer: VecDeque<Stream>,
+}
+
+impl GpmfIter {
+    pub fn new(path: &Path) -> Result<Self, GpmfError> {
+        let mut mp4 = Mp4::new(path)?;
+        let (creation_time, duration) = mp4.time(false)?;
+
+        let (sender, receiver) = sync_channel(SAMPLE_BUFFER);
+
+        thread::spawn(move || {
+            let mut track = match mp4.track(GOPRO_METADATA_HANDLER, false) {
+                Ok(track) => track,
+                Err(err) => {
+                    _ = sender.send(Err(err.into()));
+                    return
+                }
+            };
+
+            for result in track.samples() {
+                let streams = result
+                    .map_err(GpmfError::from)
+                    .and_then(|mut sample| {
+                        let len = sample.len();
+                        let ts = Timestamp::from(&sample);
+                        // Absolute position of sample in MP4
+                        let offset = sample.offset();
+                        Ok(Stream::new(&mut sample, len)?
+                            .into_iter()
+                            .map(|mut s| {
+                                s.offset_location(offset);
+                                s.with_time(&ts)
+                            })
+                            .collect::<Vec<Stream>>())
+                    });
+
+                let is_err = streams.is_err();
+
+                // Receiver dropped, i.e. iteration stopped early
+                if sender.send(streams).is_err() || is_err {
+                    break
+                }
+            }
+        });
+
+        Ok(Self {
+            creation_time,
+            duration,
+            source: path.to_owned(),
+            receiver,
+            buffer: VecDeque::new(),
+        })
+    }
+}
+
+impl Iterator for GpmfIter {
+    type Item = Result<Stream, GpmfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(stream) = self.buffer.pop_front() {
+                return Some(Ok(stream))
+            }
+
+            // Err if sender has dropped, i.e. all samples have been read
+            match self.receiver.recv().ok()? {
+                Ok(streams) => self.buffer.extend(streams),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
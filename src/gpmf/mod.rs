@@ -3,14 +3,34 @@
 pub mod gpmf;
 // pub mod mdat; // WIP
 pub mod fourcc;
+pub mod frames;
 pub mod header;
+pub(crate) mod huffman;
+pub mod iter;
+pub mod options;
+pub mod resample;
+pub mod select;
+pub(crate) mod slice;
 pub mod stream;
+pub mod stream_ref;
+pub mod time_model;
+pub mod timed;
 pub mod timestamp;
+pub mod validate;
 pub mod value;
 
 pub use gpmf::Gpmf;
+pub use iter::GpmfIter;
+pub use options::{Diagnostic, ParseOptions};
+pub use resample::{Column, Interpolation, Resampled};
 pub use fourcc::FourCC;
-pub use stream::{Stream, StreamType};
+pub use frames::FrameMap;
+pub use select::{Selection, Selector};
+pub use stream::{Location, Stream, StreamType};
+pub use stream_ref::{StreamRef, StreamRefIter, ValueRef, ValueRefIter};
+pub use time_model::{TimeModel, TimeSource};
+pub use timed::{TimedSample, TimedSamples};
 pub use timestamp::Timestamp;
+pub use validate::{Severity, ValidationIssue};
 pub use value::Value;
 pub use header::Header;
@@ -0,0 +1,107 @@
+//! Options for parsing GPMF into `Stream`s, and diagnostics
+//! for recoverable errors encountered in lenient mode.
+
+use std::fmt::Display;
+
+use super::FourCC;
+
+/// Options for parsing GPMF into `Stream`s.
+///
+/// The default is strict parsing, i.e. the first error
+/// fails the entire parse, with limits that
+/// valid GoPro GPMF data stays well within.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// Recover from corrupt data, e.g. crash truncated clips,
+    /// rather than return an error:
+    /// - KLVs with data that could not be read are stored as `Value::Invalid`.
+    /// - Truncated containers are parsed up until the end of available data.
+    /// - On invalid headers, parsing resyncs to the next valid `DEVC`,
+    ///   or the next MP4 sample.
+    ///
+    /// Each recovery is logged as a `Diagnostic`.
+    pub lenient: bool,
+    /// Max nesting depth for containers.
+    /// GoPro GPMF is usually two levels deep (`DEVC` -> `STRM`).
+    /// Raises `GpmfError::RecurseDepthExceeded`.
+    pub max_depth: usize,
+    /// Max total number of `Value`s for a single parse,
+    /// i.e. per MP4 sample for MP4-files,
+    /// or for the entire file for "raw" GPMF-files.
+    /// Raises `GpmfError::MaxValuesExceeded`.
+    pub max_values: usize,
+    /// Max data size in bytes for a single KLV
+    /// (`basesize * repeats`), including containers
+    /// and uncompressed size for compressed data.
+    /// Raises `GpmfError::MaxKlvSizeExceeded`.
+    pub max_klv_size: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            lenient: false,
+            max_depth: 8,
+            max_values: 20_000_000,
+            max_klv_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Options for lenient parsing.
+    pub fn lenient() -> Self {
+        Self {
+            lenient: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Recoverable error encountered when parsing
+/// GPMF in lenient mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// MP4 sample index, if source is an MP4 file.
+    pub sample: Option<usize>,
+    /// Byte offset for the KLV header, relative to the
+    /// start of the MP4 sample, or the start of the GPMF data
+    /// for other sources.
+    pub offset: u64,
+    /// Four CC for the affected KLV, if the header could be read.
+    pub fourcc: Option<FourCC>,
+    /// Description of the error and how it was handled.
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(sample) = self.sample {
+            write!(f, "sample {sample}, ")?;
+        }
+        write!(f, "@{}", self.offset)?;
+        if let Some(fourcc) = &self.fourcc {
+            write!(f, " {}", fourcc.to_str())?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Diagnostic {
+    pub(crate) fn new(offset: u64, fourcc: Option<&FourCC>, message: impl Into<String>) -> Self {
+        Self {
+            sample: None,
+            offset,
+            fourcc: fourcc.cloned(),
+            message: message.into(),
+        }
+    }
+
+    /// Sets MP4 sample index.
+    pub(crate) fn with_sample(self, sample: usize) -> Self {
+        Self {
+            sample: Some(sample),
+            ..self
+        }
+    }
+}
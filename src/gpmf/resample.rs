@@ -0,0 +1,351 @@
+//! Resampling of data streams with different sample rates
+//! (e.g. 200Hz `ACCL`, 10Hz `GPS9`, frame rate `CORI`)
+//! onto a common time line, as a single table with one row per time
+//! and one column per `DataType`. See `Gpmf::resample()`.
+//!
+//! ```rs
+//! use gpmf_rs::{DataType, Gpmf, Interpolation};
+//! use std::path::Path;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! let table = gpmf.resample(
+//!     &[DataType::Accelerometer, DataType::Gyroscope, DataType::CameraOrientation],
+//!     50.,
+//!     Interpolation::Slerp
+//! )?;
+//! for (time, row) in table.rows() {
+//!     println!("{:.3} {row:?}", time.as_seconds_f64());
+//! }
+//! ```
+
+use time::Duration;
+
+use crate::{DataType, GpmfError};
+use super::{Stream, TimedSample, TimedSamples};
+
+/// Interpolation method for `Gpmf::resample()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Closest sample in time.
+    Nearest,
+    /// Linear interpolation between
+    /// the two closest samples.
+    Linear,
+    /// Spherical linear interpolation for quaternions
+    /// (4 values per sample, `w, x, y, z`), e.g. `CORI`, `IORI`.
+    /// Other data is interpolated linearly.
+    Slerp,
+}
+
+/// Single resampled data type.
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub data_type: DataType,
+    /// SI units (`SIUN`) or display units (`UNIT`).
+    pub units: Option<Vec<String>>,
+    /// Number of values per row.
+    pub width: usize,
+    /// Resampled values, one entry per time in `Resampled::times`.
+    pub values: Vec<Vec<f64>>,
+}
+
+/// Data types resampled onto a common time line.
+#[derive(Debug, Clone)]
+pub struct Resampled {
+    /// Sample rate in Hz.
+    pub rate: f64,
+    /// Time for each row, relative to video start.
+    pub times: Vec<Duration>,
+    /// One column per data type, in requested order.
+    pub columns: Vec<Column>,
+}
+
+impl Resampled {
+    /// Resamples data types in `devc_streams` at `rate` Hz.
+    /// The time line covers the time span where all
+    /// data types have samples, so that all rows are complete.
+    ///
+    /// Raises `GpmfError::NoData` if a data type is not
+    /// present or if time spans do not overlap.
+    pub fn new(
+        devc_streams: &[Stream],
+        data_types: &[DataType],
+        rate: f64,
+        method: Interpolation
+    ) -> Result<Self, GpmfError> {
+        if !rate.is_finite() || rate <= 0. {
+            return Err(GpmfError::InvalidSampleRate(rate))
+        }
+
+        // First device only, i.e. the camera
+        let timed = data_types.iter()
+            .map(|dt| TimedSamples::from_data_type(devc_streams, dt)
+                .into_iter()
+                .find(|t| !t.is_empty())
+                .ok_or(GpmfError::NoData))
+            .collect::<Result<Vec<_>, GpmfError>>()?;
+
+        let start = timed.iter().filter_map(|t| t.start()).max().ok_or(GpmfError::NoData)?;
+        let end = timed.iter().filter_map(|t| t.end()).min().ok_or(GpmfError::NoData)?;
+        if end < start {
+            return Err(GpmfError::NoData)
+        }
+
+        let count = ((end - start).as_seconds_f64() * rate).floor() as usize + 1;
+        let times: Vec<Duration> = (0..count)
+            .map(|i| start + Duration::seconds_f64(i as f64 / rate))
+            .collect();
+
+        let columns = data_types.iter()
+            .zip(timed.iter())
+            .map(|(data_type, samples)| {
+                let width = samples.samples.iter()
+                    .map(|s| s.values.len())
+                    .max()
+                    .unwrap_or(0);
+                // Slerp only applies to quaternions
+                let method = match (method, width) {
+                    (Interpolation::Slerp, 4) => Interpolation::Slerp,
+                    (Interpolation::Slerp, _) => Interpolation::Linear,
+                    (m, _) => m,
+                };
+                Column {
+                    data_type: data_type.to_owned(),
+                    units: samples.units.to_owned(),
+                    width,
+                    values: times.iter()
+                        .map(|t| interpolate(&samples.samples, *t, method))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            rate,
+            times,
+            columns,
+        })
+    }
+
+    /// Returns number of rows.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Returns `true` if there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Returns column headers, one per value,
+    /// e.g. `Accelerometer 1`, `Accelerometer 2`...
+    pub fn headers(&self) -> Vec<String> {
+        self.columns.iter()
+            .flat_map(|c| (1..=c.width)
+                .map(|i| format!("{} {i}", c.data_type.to_str())))
+            .collect()
+    }
+
+    /// Returns row at index `index`, with values for all
+    /// columns concatenated.
+    pub fn row(&self, index: usize) -> Option<(Duration, Vec<f64>)> {
+        let time = *self.times.get(index)?;
+        let values = self.columns.iter()
+            .flat_map(|c| c.values[index].iter().copied())
+            .collect();
+        Some((time, values))
+    }
+
+    /// Iterates over rows, with values for all
+    /// columns concatenated.
+    pub fn rows(&self) -> impl Iterator<Item = (Duration, Vec<f64>)> + '_ {
+        (0..self.len()).filter_map(|i| self.row(i))
+    }
+}
+
+/// Interpolates value at `time`. `samples` must be sorted by time.
+fn interpolate(samples: &[TimedSample], time: Duration, method: Interpolation) -> Vec<f64> {
+    // Index of first sample at or after `time`
+    let i = samples.partition_point(|s| s.time < time);
+    let (a, b) = match (i.checked_sub(1).and_then(|j| samples.get(j)), samples.get(i)) {
+        (Some(a), Some(b)) => (a, b),
+        (Some(s), None) | (None, Some(s)) => return s.values.to_owned(),
+        (None, None) => return Vec::new(),
+    };
+
+    let span = (b.time - a.time).as_seconds_f64();
+    let frac = match span > 0. {
+        true => (time - a.time).as_seconds_f64() / span,
+        false => 0.,
+    };
+
+    match method {
+        Interpolation::Nearest => match frac < 0.5 {
+            true => a.values.to_owned(),
+            false => b.values.to_owned(),
+        },
+        Interpolation::Linear => lerp(&a.values, &b.values, frac),
+        Interpolation::Slerp => slerp(&a.values, &b.values, frac),
+    }
+}
+
+fn lerp(a: &[f64], b: &[f64], frac: f64) -> Vec<f64> {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| a + (b - a) * frac)
+        .collect()
+}
+
+/// Spherical linear interpolation between two quaternions.
+fn slerp(a: &[f64], b: &[f64], frac: f64) -> Vec<f64> {
+    let norm = |q: &[f64]| -> Vec<f64> {
+        let len = q.iter().map(|v| v * v).sum::<f64>().sqrt();
+        match len > 0. {
+            true => q.iter().map(|v| v / len).collect(),
+            false => q.to_vec(),
+        }
+    };
+    let a = norm(a);
+    let mut b = norm(b);
+
+    // Take shortest path
+    let mut dot: f64 = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum();
+    if dot < 0. {
+        b.iter_mut().for_each(|v| *v = -*v);
+        dot = -dot;
+    }
+
+    // Nearly parallel, linear is accurate enough and avoids div by 0
+    if dot > 0.9995 {
+        return norm(&lerp(&a, &b, frac))
+    }
+
+    let theta = dot.min(1.).acos();
+    let sin = theta.sin();
+    let wa = ((1. - frac) * theta).sin() / sin;
+    let wb = (frac * theta).sin() / sin;
+
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| wa * a + wb * b)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::{Interpolation, Resampled, interpolate, slerp};
+    use crate::{DataType, GpmfError, Stream, TimedSample};
+    use crate::gpmf::Value;
+    use crate::tests::{container, string, timed, values};
+
+    /// `DEVC` from 0 to 1 s with `samples` spread evenly.
+    fn devc(name: &str, fourcc: &str, samples: &[i16]) -> Stream {
+        timed(container("DEVC", vec![
+            container("STRM", vec![
+                string("STNM", name),
+                values(fourcc, b's', 2, samples.iter().map(|s| Value::Sint16(vec![*s])).collect()),
+            ]),
+        ]), 0., 1.)
+    }
+
+    /// Accelerometer at 0, 0.25, 0.5, 0.75 s,
+    /// gyroscope at 0, 0.5 s.
+    fn streams() -> Vec<Stream> {
+        vec![
+            devc("Accelerometer", "ACCL", &[0, 10, 20, 30]),
+            devc("Gyroscope", "GYRO", &[0, 1]),
+        ]
+    }
+
+    fn resample(rate: f64, method: Interpolation) -> Result<Resampled, GpmfError> {
+        Resampled::new(&streams(), &[DataType::Accelerometer, DataType::Gyroscope], rate, method)
+    }
+
+    fn sample(seconds: f64, values: &[f64]) -> TimedSample {
+        TimedSample {
+            time: Duration::seconds_f64(seconds),
+            values: values.to_vec(),
+        }
+    }
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len(), "{a:?} {b:?}");
+        assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-9), "{a:?} {b:?}");
+    }
+
+    #[test]
+    fn resample_exact() {
+        // Overlap 0 to 0.5 s, rows at sample times for accelerometer
+        let table = resample(4., Interpolation::Linear).unwrap();
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.headers(), vec!["Accelerometer 1", "Gyroscope 1"]);
+        let rows: Vec<_> = table.rows().collect();
+        assert_eq!(rows[1].0, Duration::milliseconds(250));
+        assert_close(&rows[0].1, &[0., 0.]);
+        assert_close(&rows[1].1, &[10., 0.5]);
+        assert_close(&rows[2].1, &[20., 1.]);
+    }
+
+    #[test]
+    fn resample_upsample() {
+        let table = resample(8., Interpolation::Linear).unwrap();
+
+        assert_eq!(table.len(), 5);
+        let accl: Vec<f64> = table.columns[0].values.iter().map(|v| v[0]).collect();
+        assert_close(&accl, &[0., 5., 10., 15., 20.]);
+
+        let table = resample(8., Interpolation::Nearest).unwrap();
+        let gyro: Vec<f64> = table.columns[1].values.iter().map(|v| v[0]).collect();
+        assert_close(&gyro, &[0., 0., 1., 1., 1.]);
+    }
+
+    #[test]
+    fn resample_downsample() {
+        let table = resample(2., Interpolation::Linear).unwrap();
+
+        assert_eq!(table.times, vec![Duration::ZERO, Duration::milliseconds(500)]);
+        assert_close(&table.row(1).unwrap().1, &[20., 1.]);
+        assert!(table.row(2).is_none());
+    }
+
+    #[test]
+    fn resample_empty() {
+        assert!(matches!(
+            Resampled::new(&[], &[DataType::Accelerometer], 10., Interpolation::Linear),
+            Err(GpmfError::NoData)
+        ));
+        assert!(matches!(
+            Resampled::new(&streams(), &[DataType::Gps5], 10., Interpolation::Linear),
+            Err(GpmfError::NoData)
+        ));
+        assert!(matches!(resample(0., Interpolation::Linear), Err(GpmfError::InvalidSampleRate(_))));
+        assert!(matches!(resample(f64::NAN, Interpolation::Linear), Err(GpmfError::InvalidSampleRate(_))));
+        assert!(interpolate(&[], Duration::ZERO, Interpolation::Linear).is_empty());
+    }
+
+    #[test]
+    fn interpolate_edges() {
+        let samples = [sample(1., &[1.]), sample(2., &[3.])];
+        // Before first and after last sample
+        assert_eq!(interpolate(&samples, Duration::ZERO, Interpolation::Linear), vec![1.]);
+        assert_eq!(interpolate(&samples, Duration::seconds(3), Interpolation::Linear), vec![3.]);
+        // At sample times
+        assert_eq!(interpolate(&samples, Duration::seconds(1), Interpolation::Linear), vec![1.]);
+        assert_eq!(interpolate(&samples, Duration::seconds(2), Interpolation::Linear), vec![3.]);
+        assert_eq!(interpolate(&samples, Duration::seconds_f64(1.25), Interpolation::Linear), vec![1.5]);
+    }
+
+    #[test]
+    fn interpolate_slerp() {
+        let (c, s) = (45_f64.to_radians().cos(), 45_f64.to_radians().sin());
+        // Identity to 90 degrees around z
+        let q = slerp(&[1., 0., 0., 0.], &[c, 0., 0., s], 0.5);
+        let half = 22.5_f64.to_radians();
+        assert_close(&q, &[half.cos(), 0., 0., half.sin()]);
+        // Shortest path for negated quaternion
+        assert_close(&slerp(&[1., 0., 0., 0.], &[-c, 0., 0., -s], 0.5), &q);
+    }
+}
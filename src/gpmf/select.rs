@@ -0,0 +1,373 @@
+//! Selector queries over the GPMF tree, similar to a small subset of XPath.
+//!
+//! ```text
+//! selector  = step *( "/" step )
+//! step      = fourcc *( "[" predicate "]" )
+//! fourcc    = 4 ASCII alphanumerics, or "*" for any Four CC
+//! predicate = fourcc [ op quoted ]
+//! op        = "=" / "!=" / "~"
+//! ```
+//!
+//! The first step matches top-level streams (usually `DEVC`),
+//! each following step matches direct child streams.
+//! Predicates test the first value of a direct child stream
+//! with the specified Four CC:
+//! - `[DVNM]`: child exists.
+//! - `[DVNM='Hero11 Black']`: string equals text, or number equals numerical text.
+//! - `[DVNM!='Hero11 Black']`: child exists, but does not equal text.
+//! - `[STNM~'gyro']`: contains text, case insensitive.
+//!
+//! ```rs
+//! use gpmf_rs::Gpmf;
+//! use std::path::Path;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! for sel in gpmf.select("DEVC[DVNM='Hero11 Black']/STRM[STNM~'Gyro']/GYRO")? {
+//!     println!("{:?} {:?} {:?}", sel.time, sel.scale, sel.stream.to_vec_f64());
+//! }
+//! ```
+
+use crate::GpmfError;
+use super::{FourCC, Stream, StreamType, Timestamp, Value};
+
+/// Comparison operator for predicates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    /// `=`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `~`, case insensitive substring.
+    Contains,
+}
+
+/// Tests a direct child stream.
+#[derive(Debug, Clone, PartialEq)]
+struct Predicate {
+    fourcc: FourCC,
+    test: Option<(Op, String)>,
+}
+
+impl Predicate {
+    fn matches(&self, stream: &Stream) -> bool {
+        let child = match &stream.streams {
+            StreamType::Nested(streams) => streams.iter().find(|s| s.has_fourcc(&self.fourcc)),
+            StreamType::Values(_) => None,
+        };
+        let Some(child) = child else {
+            return false
+        };
+
+        let Some((op, text)) = &self.test else {
+            return true
+        };
+
+        let value = child.first_value();
+        let string: Option<String> = value.and_then(|v| v.into());
+        let number: Option<f64> = value.and_then(|v| v.into());
+
+        let equals = match (&string, number) {
+            (Some(s), _) => s.trim() == text,
+            (None, Some(n)) => text.parse::<f64>().is_ok_and(|t| t == n),
+            _ => false,
+        };
+
+        match op {
+            Op::Eq => equals,
+            Op::Ne => !equals,
+            Op::Contains => string
+                .or_else(|| number.map(|n| n.to_string()))
+                .is_some_and(|s| s.to_lowercase().contains(&text.to_lowercase())),
+        }
+    }
+}
+
+/// Single step in a selector path.
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    /// `None` matches any Four CC.
+    fourcc: Option<FourCC>,
+    predicates: Vec<Predicate>,
+}
+
+impl Step {
+    fn matches(&self, stream: &Stream) -> bool {
+        self.fourcc.as_ref().is_none_or(|f| stream.has_fourcc(f))
+        && self.predicates.iter().all(|p| p.matches(stream))
+    }
+}
+
+/// Parsed selector query. See module documentation for syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+/// Stream matched by a `Selector`, together with inherited
+/// timestamp and related metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection<'a> {
+    /// Matched stream.
+    pub stream: &'a Stream,
+    /// Timestamp of the closest parent stream with
+    /// a timestamp set (usually `DEVC`), or of the matched stream itself.
+    pub time: Option<Timestamp>,
+    /// Scale values (`SCAL`).
+    pub scale: Option<Vec<f64>>,
+    /// SI units (`SIUN`).
+    pub units: Option<Vec<String>>,
+    /// Input orientation (`ORIN`).
+    pub orientation: Option<String>,
+}
+
+impl<'a> Selection<'a> {
+    /// Metadata is read from the children of `stream` if it is
+    /// a container (e.g. `STRM`), otherwise from its `siblings`.
+    fn new(stream: &'a Stream, siblings: &'a [Stream], time: Option<&Timestamp>) -> Self {
+        let metadata = match &stream.streams {
+            StreamType::Nested(streams) => streams.as_slice(),
+            StreamType::Values(_) => siblings,
+        };
+        let find = |fourcc: &FourCC| metadata.iter().find(|s| s.has_fourcc(fourcc));
+
+        Self {
+            stream,
+            time: time.cloned(),
+            scale: find(&FourCC::SCAL)
+                .and_then(|s| s.to_vec_f64())
+                .map(|v| v.into_iter().flatten().collect()),
+            units: find(&FourCC::SIUN)
+                .and_then(|s| s.values())
+                .map(|v| v.iter().filter_map(|s| s.into()).collect()),
+            orientation: find(&FourCC::ORIN)
+                .and_then(|s| s.first_value())
+                .and_then(|v: &Value| v.into()),
+        }
+    }
+}
+
+fn selector_error(msg: impl Into<String>) -> GpmfError {
+    GpmfError::InvalidSelector(msg.into())
+}
+
+/// Parses a Four CC at `pos`, or `None` for a wildcard (`*`).
+fn parse_fourcc(chars: &[char], pos: &mut usize, wildcard: bool) -> Result<Option<FourCC>, GpmfError> {
+    if wildcard && chars.get(*pos) == Some(&'*') {
+        *pos += 1;
+        return Ok(None)
+    }
+    let fourcc = chars.get(*pos..*pos + 4)
+        .filter(|cs| cs.iter().all(|c| c.is_ascii_alphanumeric()))
+        .ok_or_else(|| selector_error(format!("expected Four CC at position {pos}")))?;
+    *pos += 4;
+    let bytes: Vec<u8> = fourcc.iter().map(|c| *c as u8).collect();
+    Ok(Some(FourCC::from_slice(&bytes)))
+}
+
+/// Parses quoted text at `pos`, single or double quotes.
+fn parse_quoted(chars: &[char], pos: &mut usize) -> Result<String, GpmfError> {
+    let quote = match chars.get(*pos) {
+        Some(q @ ('\'' | '"')) => *q,
+        _ => return Err(selector_error(format!("expected quoted text at position {pos}"))),
+    };
+    let start = *pos + 1;
+    let len = chars[start..].iter()
+        .position(|c| *c == quote)
+        .ok_or_else(|| selector_error(format!("unterminated quote at position {pos}")))?;
+    *pos = start + len + 1;
+    Ok(chars[start..start + len].iter().collect())
+}
+
+impl Selector {
+    /// Parses selector query, e.g.
+    /// `DEVC[DVNM='Hero11 Black']/STRM[STNM~'Gyro']/GYRO`.
+    pub fn new(selector: &str) -> Result<Self, GpmfError> {
+        let chars: Vec<char> = selector.trim().chars().collect();
+        let mut pos = 0;
+        let mut steps: Vec<Step> = Vec::new();
+
+        loop {
+            let fourcc = parse_fourcc(&chars, &mut pos, true)?;
+            let mut predicates: Vec<Predicate> = Vec::new();
+
+            while chars.get(pos) == Some(&'[') {
+                pos += 1;
+                let Some(fourcc) = parse_fourcc(&chars, &mut pos, false)? else {
+                    return Err(selector_error(format!("wildcard not allowed in predicate at position {pos}")))
+                };
+                let op = match (chars.get(pos), chars.get(pos + 1)) {
+                    (Some('='), _) => Some((Op::Eq, 1)),
+                    (Some('!'), Some('=')) => Some((Op::Ne, 2)),
+                    (Some('~'), _) => Some((Op::Contains, 1)),
+                    _ => None,
+                };
+                let test = match op {
+                    Some((op, len)) => {
+                        pos += len;
+                        Some((op, parse_quoted(&chars, &mut pos)?))
+                    },
+                    None => None,
+                };
+                if chars.get(pos) != Some(&']') {
+                    return Err(selector_error(format!("expected ']' at position {pos}")))
+                }
+                pos += 1;
+                predicates.push(Predicate { fourcc, test });
+            }
+
+            steps.push(Step { fourcc, predicates });
+
+            match chars.get(pos) {
+                None => break,
+                Some('/') => pos += 1,
+                Some(c) => return Err(selector_error(format!("unexpected '{c}' at position {pos}"))),
+            }
+        }
+
+        Ok(Self { steps })
+    }
+
+    /// Returns all streams matching selector,
+    /// with the first step matching `streams`.
+    pub fn select<'a>(&self, streams: &'a [Stream]) -> Vec<Selection<'a>> {
+        let mut selections: Vec<Selection<'a>> = Vec::new();
+        self.select_step(streams, 0, None, &mut selections);
+        selections
+    }
+
+    fn select_step<'a>(
+        &self,
+        streams: &'a [Stream],
+        step: usize,
+        time: Option<&'a Timestamp>,
+        selections: &mut Vec<Selection<'a>>
+    ) {
+        let Some(current) = self.steps.get(step) else {
+            return
+        };
+
+        for stream in streams.iter().filter(|s| current.matches(s)) {
+            // Timestamps are only set for DEVC, inherit from parent
+            let time = stream.time.as_ref().or(time);
+
+            if step + 1 == self.steps.len() {
+                selections.push(Selection::new(stream, streams, time));
+            } else if let StreamType::Nested(children) = &stream.streams {
+                self.select_step(children, step + 1, time, selections);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Op, Predicate, Selector, Step};
+    use crate::{FourCC, Stream};
+    use crate::gpmf::Value;
+    use crate::tests::{container, string, timed, uint32, values};
+
+    fn devc(name: &str, relative: f64) -> Stream {
+        let strm = |stnm: &str, fourcc: &str, scale: i16| container("STRM", vec![
+            string("STNM", stnm),
+            values("SCAL", b's', 2, vec![Value::Sint16(vec![scale])]),
+            string("SIUN", "m/s2"),
+            string("ORIN", "ZXY"),
+            values(fourcc, b's', 6, vec![Value::Sint16(vec![1, 2, 3])]),
+        ]);
+        let devc = container("DEVC", vec![
+            uint32("DVID", 1),
+            string("DVNM", name),
+            strm("Accelerometer", "ACCL", 418),
+            strm("Gyroscope", "GYRO", 939),
+        ]);
+        timed(devc, relative, 1.0)
+    }
+
+    fn selected<'a>(selector: &str, streams: &'a [Stream]) -> Vec<&'a Stream> {
+        Selector::new(selector).unwrap()
+            .select(streams)
+            .into_iter()
+            .map(|s| s.stream)
+            .collect()
+    }
+
+    #[test]
+    fn selector_parse() {
+        let selector = Selector::new(" DEVC[DVNM='Hero11 Black']/STRM[STNM~\"gyro\"][TSMP]/* ").unwrap();
+        assert_eq!(selector.steps, vec![
+            Step {
+                fourcc: Some(FourCC::from_str("DEVC")),
+                predicates: vec![Predicate {
+                    fourcc: FourCC::from_str("DVNM"),
+                    test: Some((Op::Eq, "Hero11 Black".to_owned())),
+                }],
+            },
+            Step {
+                fourcc: Some(FourCC::from_str("STRM")),
+                predicates: vec![
+                    Predicate {
+                        fourcc: FourCC::from_str("STNM"),
+                        test: Some((Op::Contains, "gyro".to_owned())),
+                    },
+                    Predicate {
+                        fourcc: FourCC::from_str("TSMP"),
+                        test: None,
+                    },
+                ],
+            },
+            Step { fourcc: None, predicates: vec![] },
+        ]);
+
+        let selector = Selector::new("*[DVID!='1']").unwrap();
+        assert_eq!(selector.steps[0].fourcc, None);
+        assert_eq!(selector.steps[0].predicates[0].test, Some((Op::Ne, "1".to_owned())));
+    }
+
+    #[test]
+    fn selector_parse_malformed() {
+        for selector in [
+            "",
+            "DEV",
+            "DEVC/",
+            "DEVC//STRM",
+            "DEV!",
+            "DEVC[*]",
+            "DEVC[DVNM",
+            "DEVC[DVNM=Hero]",
+            "DEVC[DVNM='Hero]",
+            "DEVC[DVNM='Hero'",
+            "DEVC[DVNM>'Hero']",
+            "DEVC STRM",
+        ] {
+            assert!(Selector::new(selector).is_err(), "{selector:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn selector_match() {
+        let streams = vec![devc("Hero11 Black", 0.0), devc("Hero9 Black", 1.0)];
+
+        let gyro = Selector::new("DEVC[DVNM='Hero11 Black']/STRM[STNM~'GYRO']/GYRO").unwrap()
+            .select(&streams);
+        assert_eq!(gyro.len(), 1);
+        let gyro = &gyro[0];
+        assert!(gyro.stream.has_fourcc(&FourCC::from_str("GYRO")));
+        assert_eq!(gyro.time, streams[0].time);
+        assert_eq!(gyro.scale, Some(vec![939.0]));
+        assert_eq!(gyro.units, Some(vec!["m/s2".to_owned()]));
+        assert_eq!(gyro.orientation.as_deref(), Some("ZXY"));
+
+        // Wildcard step, numerical and negated predicates
+        assert_eq!(selected("DEVC[DVID='1']/STRM/*", &streams).len(), 20);
+        assert_eq!(selected("*[DVNM!='Hero11 Black']/STRM", &streams).len(), 2);
+        assert_eq!(selected("DEVC[DVNM!='Hero11 Black']", &streams), vec![&streams[1]]);
+        assert!(selected("DEVC[DVID='2']", &streams).is_empty());
+
+        // Predicates only test direct children
+        assert!(selected("DEVC[STNM]", &streams).is_empty());
+        assert_eq!(selected("DEVC/STRM[SIUN='m/s2']/ACCL", &streams).len(), 2);
+
+        // Path deeper than tree
+        assert!(selected("DEVC/STRM/ACCL/ACCL", &streams).is_empty());
+    }
+}
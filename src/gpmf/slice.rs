@@ -0,0 +1,270 @@
+//! Time range slicing of `DEVC` streams. See `Gpmf::slice()`.
+
+use time::{Duration, PrimitiveDateTime, format_description};
+
+use super::{FourCC, Header, Stream, StreamType, Timestamp, Value};
+
+/// Returns `DEVC` stream trimmed to the time range `start` (inclusive)
+/// to `end` (exclusive), relative to video start.
+/// Returns `None` if the stream has no timestamp, or does not
+/// overlap the time range.
+///
+/// Data streams (the last stream in each `STRM`) in a `DEVC` that only
+/// partially overlaps the range are split at sample boundaries,
+/// with samples assumed to be evenly spread across the `DEVC` duration.
+/// `TSMP`, `STMP` and `GPSU` are adjusted to match the remaining samples.
+///
+/// Modified streams have their container headers re-calculated
+/// and their byte location (`Stream::location`) removed,
+/// since these no longer correspond to the source.
+pub(crate) fn slice_devc(devc: &Stream, start: Duration, end: Duration) -> Option<Stream> {
+    let time = devc.time.as_ref()?;
+    let (t0, t1) = (time.relative, time.relative + time.duration);
+
+    if t0 >= end || t1 <= start {
+        return None
+    }
+    if t0 >= start && t1 <= end {
+        return Some(devc.to_owned())
+    }
+
+    let StreamType::Nested(children) = &devc.streams else {
+        return None
+    };
+
+    let streams = children.iter()
+        .map(|s| match s.has_fourcc(&FourCC::STRM) {
+            true => slice_strm(s, time, start, end),
+            false => s.to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    let (start, end) = (start.max(t0), end.min(t1));
+
+    let devc = match streams == **children {
+        true => devc.to_owned(),
+        false => container(devc, streams),
+    };
+
+    Some(Stream {
+        time: Some(Timestamp {
+            relative: start,
+            duration: end - start,
+        }),
+        ..devc
+    })
+}
+
+/// Trims data stream in `STRM` to samples within time range.
+fn slice_strm(strm: &Stream, time: &Timestamp, start: Duration, end: Duration) -> Stream {
+    let StreamType::Nested(children) = &strm.streams else {
+        return strm.to_owned()
+    };
+    let Some(data) = children.last() else {
+        return strm.to_owned()
+    };
+    let StreamType::Values(values) = &data.streams else {
+        return strm.to_owned()
+    };
+
+    // Strings and containers can not be split
+    let count = values.len();
+    if count == 0 || matches!(data.header.basetype, b'c' | 0) {
+        return strm.to_owned()
+    }
+
+    // Index of first sample at or after `t`
+    let step = time.duration.as_seconds_f64() / count as f64;
+    let index = |t: Duration| -> usize {
+        let offset = (t - time.relative).as_seconds_f64();
+        match step > 0. {
+            true => ((offset / step).ceil().max(0.) as usize).min(count),
+            false => 0,
+        }
+    };
+    let (first, last) = (index(start), index(end).max(index(start)));
+
+    // Unchanged, keep original header and location
+    if first == 0 && last == count {
+        return strm.to_owned()
+    }
+
+    let data = Stream {
+        header: Header::from_parts(
+            data.fourcc().to_owned(),
+            data.header.basetype,
+            data.header.basesize as u8,
+            (last - first) as u16
+        ),
+        streams: StreamType::Values(values[first..last].to_vec()),
+        time: data.time.to_owned(),
+        location: None,
+    };
+
+    let streams = children[..children.len() - 1].iter()
+        .map(|s| match s.fourcc() {
+            // Total samples delivered, exclude samples removed at the end
+            FourCC::TSMP => adjust_first(s, -((count - last) as f64)),
+            // Microsecond timestamp for first sample
+            FourCC::STMP => adjust_first(s, first as f64 * step * 1_000_000.),
+            // Datetime for first GPS5 sample
+            FourCC::GPSU => shift_datetime(s, Duration::seconds_f64(first as f64 * step)),
+            _ => s.to_owned(),
+        })
+        .chain(std::iter::once(data))
+        .collect::<Vec<_>>();
+
+    container(strm, streams)
+}
+
+/// Returns container with new child streams,
+/// re-calculated header and no location.
+fn container(stream: &Stream, streams: Vec<Stream>) -> Stream {
+    // Child headers are up to date, so size can be derived from these
+    let len: u32 = streams.iter()
+        .map(|s| 8 + s.size(true))
+        .sum();
+    Stream {
+        // Size only decreases when sliced, so this should not fail
+        header: Header::nested(stream.fourcc(), len as usize)
+            .unwrap_or_else(|_| stream.header.to_owned()),
+        streams: StreamType::Nested(Box::new(streams)),
+        time: stream.time.to_owned(),
+        location: None,
+    }
+}
+
+/// Adds `delta` to first numerical value.
+fn adjust_first(stream: &Stream, delta: f64) -> Stream {
+    let StreamType::Values(values) = &stream.streams else {
+        return stream.to_owned()
+    };
+    let mut values = values.to_owned();
+    if let Some(value) = values.first_mut() {
+        match value {
+            Value::Uint32(v) => v.iter_mut().take(1).for_each(|n| *n = (*n as f64 + delta).max(0.) as u32),
+            Value::Sint32(v) => v.iter_mut().take(1).for_each(|n| *n = (*n as f64 + delta) as i32),
+            Value::Uint64(v) => v.iter_mut().take(1).for_each(|n| *n = (*n as f64 + delta).max(0.) as u64),
+            Value::Sint64(v) => v.iter_mut().take(1).for_each(|n| *n = (*n as f64 + delta) as i64),
+            _ => (),
+        }
+    }
+    Stream {
+        streams: StreamType::Values(values),
+        location: None,
+        ..stream.to_owned()
+    }
+}
+
+/// Adds `delta` to first datetime value (`yymmddhhmmss.sss`).
+fn shift_datetime(stream: &Stream, delta: Duration) -> Stream {
+    let StreamType::Values(values) = &stream.streams else {
+        return stream.to_owned()
+    };
+    let Ok(format) = format_description::parse(
+        "[year repr:last_two][month][day][hour][minute][second].[subsecond digits:3]"
+    ) else {
+        return stream.to_owned()
+    };
+    let mut values = values.to_owned();
+    if let Some(value) = values.first_mut() {
+        let datetime: Option<PrimitiveDateTime> = (&*value).into();
+        if let Some(dt) = datetime.and_then(|dt| (dt + delta).format(&format).ok()) {
+            *value = Value::Datetime(dt);
+        }
+    }
+    Stream {
+        streams: StreamType::Values(values),
+        location: None,
+        ..stream.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::slice_devc;
+    use crate::{Gpmf, Location, Stream};
+    use crate::tests::{container, gps5_strm, timed};
+
+    fn seconds(s: f64) -> Duration {
+        Duration::seconds_f64(s)
+    }
+
+    fn samples() -> Vec<Vec<i32>> {
+        (0..4).map(|i| vec![i; 5]).collect()
+    }
+
+    /// `DEVC` at 1.0 s, duration 1.0 s, four `GPS5` samples
+    /// at 1.0, 1.25, 1.5 and 1.75 s.
+    fn devc() -> Stream {
+        let devc = timed(container("DEVC", vec![
+            gps5_strm(Some("200101120000.000"), Some(10), samples()),
+        ]), 1.0, 1.0);
+        Stream {
+            location: Some(Location { offset: 1000, size: devc.size(true) + 8 }),
+            ..devc
+        }
+    }
+
+    #[test]
+    fn slice_partial() {
+        let sliced = slice_devc(&devc(), seconds(1.25), seconds(1.75)).unwrap();
+
+        // Samples at 1.25 and 1.5, end exclusive
+        let expected = timed(container("DEVC", vec![
+            gps5_strm(Some("200101120000.250"), Some(9), samples()[1..3].to_vec()),
+        ]), 1.25, 0.5);
+        assert_eq!(sliced, expected);
+        assert!(sliced.size(false) < devc().size(false));
+        assert_eq!(sliced.location, None);
+    }
+
+    #[test]
+    fn slice_start() {
+        // Start inclusive
+        let sliced = slice_devc(&devc(), seconds(0.), seconds(1.25)).unwrap();
+        let expected = timed(container("DEVC", vec![
+            gps5_strm(Some("200101120000.000"), Some(7), samples()[..1].to_vec()),
+        ]), 1.0, 0.25);
+        assert_eq!(sliced, expected);
+    }
+
+    #[test]
+    fn slice_bounds() {
+        let devc = devc();
+        // Fully within range, unchanged including location
+        assert_eq!(slice_devc(&devc, seconds(1.0), seconds(2.0)), Some(devc.to_owned()));
+        assert_eq!(slice_devc(&devc, seconds(0.0), seconds(5.0)), Some(devc.to_owned()));
+        // DEVC end is exclusive
+        assert_eq!(slice_devc(&devc, seconds(2.0), seconds(3.0)), None);
+        assert_eq!(slice_devc(&devc, seconds(0.0), seconds(1.0)), None);
+        // No timestamp
+        assert_eq!(slice_devc(&container("DEVC", Vec::new()), seconds(0.0), seconds(5.0)), None);
+    }
+
+    #[test]
+    fn slice_gpmf() {
+        let gpmf = Gpmf {
+            creation_time: None,
+            duration: Some(seconds(2.0)),
+            streams: vec![
+                timed(container("DEVC", vec![gps5_strm(None, None, samples())]), 0.0, 1.0),
+                timed(container("DEVC", vec![gps5_strm(None, None, samples())]), 1.0, 1.0),
+            ],
+            source: Vec::new(),
+        };
+        let sliced = gpmf.slice(seconds(0.5), seconds(1.5));
+
+        assert_eq!(sliced.streams.len(), 2);
+        assert_eq!(sliced.duration, Some(seconds(1.0)));
+        let times: Vec<_> = sliced.streams.iter()
+            .map(|s| s.time.as_ref().map(|t| (t.relative, t.duration)))
+            .collect();
+        assert_eq!(times, vec![
+            Some((seconds(0.5), seconds(0.5))),
+            Some((seconds(1.0), seconds(0.5))),
+        ]);
+    }
+}
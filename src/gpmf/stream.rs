@@ -14,18 +14,19 @@
 //!       ... (points)
 //! ```
 
-use std::io::{Seek, SeekFrom, Read, BufRead};
+use std::io::{Seek, SeekFrom, Read, BufRead, Write};
 
 use log::{info, debug};
 
 use crate::{DataType, GpmfError, DeviceId};
-use super::{FourCC, Header, Value, Timestamp};
+use super::{Diagnostic, FourCC, Header, ParseOptions, Value, Timestamp, huffman};
 
 /// Core struct that preserves the GPMF structure.
 /// Contains either more `Stream`s,
 /// i.e. a container/nested stream (`Header.basetype == 0`),
 /// or data values.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stream {
     /// Stream header
     pub header: Header,
@@ -35,9 +36,29 @@ pub struct Stream {
     /// Duration since video start and "sample duration"
     /// of stream.
     pub time: Option<Timestamp>,
+    /// Byte location of the stream (KLV) in the source.
+    /// See `Location`.
+    pub location: Option<Location>,
+}
+
+/// Byte location of a GPMF KLV in its source,
+/// e.g. for locating data in a hex viewer, or in-place patching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    /// Absolute byte offset for the KLV header.
+    /// For MP4-files this is the offset of the MP4 sample
+    /// in `mdat` plus the offset inside the sample.
+    /// For "raw" GPMF-files or byte buffers, this is
+    /// the offset from the start of the data.
+    pub offset: u64,
+    /// Size in bytes, including header
+    /// and 32-bit alignment padding.
+    pub size: u32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StreamType {
     /// Container (`Header::basetype` = 0). Contains more `Stream`s.
     Nested(Box<Vec<Stream>>),
@@ -62,6 +83,38 @@ impl Stream {
         reader: &mut R,
         read_limit: usize,
     ) -> Result<Vec<Self>, GpmfError> {
+        Self::new_with_options(reader, read_limit, &ParseOptions::default(), &mut Vec::new())
+    }
+
+    /// Create new GPMF `Stream` from a reader, with parse options.
+    /// See `Stream::new()`.
+    ///
+    /// In lenient mode (`ParseOptions::lenient`), corrupt data
+    /// is skipped or truncated rather than returning an error,
+    /// and logged as `Diagnostic`s to `diagnostics`.
+    /// I/O errors, e.g. failing to seek, are still returned.
+    pub fn new_with_options<R: Read + BufRead + Seek>(
+        reader: &mut R,
+        read_limit: usize,
+        options: &ParseOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Self>, GpmfError> {
+        Self::parse(reader, read_limit, options, diagnostics, 0, &mut 0)
+    }
+
+    /// Recursive parse. `depth` is the current nesting depth,
+    /// `count` the number of `Value`s parsed so far.
+    fn parse<R: Read + BufRead + Seek>(
+        reader: &mut R,
+        read_limit: usize,
+        options: &ParseOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+        depth: usize,
+        count: &mut usize,
+    ) -> Result<Vec<Self>, GpmfError> {
+        if depth > options.max_depth {
+            return Err(GpmfError::RecurseDepthExceeded((depth, options.max_depth)))
+        }
 
         // Type definitions (BaseType::TYPE) for BaseType::COMPLEX.
         // 'TYPE' must always precede 'COMPLEX' (the GPMF type `?`). E.g. TYPE = "cF" results in
@@ -74,7 +127,19 @@ impl Stream {
 
         let max = reader.seek(SeekFrom::Current(0))? + read_limit as u64;
         while reader.seek(SeekFrom::Current(0))? < max {
-            let header = Header::new(reader)?;
+            let offset = reader.seek(SeekFrom::Current(0))?;
+
+            let header = match Header::new(reader) {
+                Ok(hdr) => hdr,
+                Err(err) if options.lenient => {
+                    diagnostics.push(Diagnostic::new(offset, None,
+                        format!("failed to read header, skipping remaining data: {err}")
+                    ));
+                    reader.seek(SeekFrom::Start(max))?;
+                    break
+                },
+                Err(err) => return Err(err),
+            };
 
             // position is only offset from start of current DEVC, not entire MP4
             debug!("@{} {header:3?} | LEN: {}",
@@ -91,8 +156,87 @@ impl Stream {
                 break
             }
 
+            // Bytes left in current container after header
+            let remaining = max.saturating_sub(offset + 8);
+
+            // Limit size for untrusted input
+            let size = header.size(false) as usize;
+            if size > options.max_klv_size {
+                return Err(GpmfError::MaxKlvSizeExceeded{max: options.max_klv_size, got: size})
+            }
+
+            // Position in source, relative to reader start.
+            // Offset to absolute position via `Stream::offset_location()`.
+            let location = Some(Location {
+                offset,
+                size: (8 + header.size(true) as u64).min(8 + remaining) as u32,
+            });
+
+            if options.lenient {
+                // Garbage header, e.g. from a corrupt sample.
+                // Resync to next top-level DEVC if possible,
+                // otherwise skip the rest of the current container.
+                if !header.is_valid() {
+                    let resync = match depth {
+                        0 => Self::resync(reader, offset + 4, max)?,
+                        _ => None,
+                    };
+                    let msg = match resync {
+                        Some(pos) => format!("invalid header, resynced to DEVC @{pos}"),
+                        None => "invalid header, skipping remaining data".to_owned(),
+                    };
+                    diagnostics.push(Diagnostic::new(offset, Some(&header.fourcc), msg));
+                    match resync {
+                        Some(pos) => {
+                            reader.seek(SeekFrom::Start(pos))?;
+                            continue
+                        },
+                        None => {
+                            reader.seek(SeekFrom::Start(max))?;
+                            break
+                        },
+                    }
+                }
+
+                // Truncated KLV. Containers are parsed up until
+                // the end of available data, anything else is invalid.
+                if header.size(false) as u64 > remaining {
+                    let streams_type = match header.basetype {
+                        0 => {
+                            diagnostics.push(Diagnostic::new(offset, Some(&header.fourcc),
+                                format!("truncated container, {} of {} bytes available", remaining, header.size(false))
+                            ));
+                            let stream = Self::parse(reader, remaining as usize, options, diagnostics, depth + 1, count)?;
+                            StreamType::Nested(Box::new(stream))
+                        },
+                        _ => {
+                            diagnostics.push(Diagnostic::new(offset, Some(&header.fourcc),
+                                format!("truncated data, {} of {} bytes available, stored as invalid", remaining, header.size(false))
+                            ));
+                            StreamType::Values(vec![Value::Invalid])
+                        }
+                    };
+                    streams.push(Self {
+                        header,
+                        streams: streams_type,
+                        time: None,
+                        location
+                    });
+                    reader.seek(SeekFrom::Start(max))?;
+                    break
+                }
+            }
+
+            if size as u64 > remaining {
+                return Err(GpmfError::ReadLimitExceeded{size, remaining: remaining as usize})
+            }
+
             let pad = header.pad;
 
+            // Position for data, used to skip
+            // values that could not be read in lenient mode.
+            let data_start = reader.seek(SeekFrom::Current(0))?;
+
             match header.basetype {
                 // 0 = Container/nested stream
                 0 => {
@@ -100,15 +244,50 @@ impl Stream {
                     // Set byte read limit to avoid embedding containers/0 in each other indefinitely if they
                     // follow directly after each other.
                     // let stream = Self::new(cursor, Some(header.size(false) as usize))?;
-                    let stream = Self::new(reader, header.size(false) as usize)?;
+                    let stream = Self::parse(reader, size, options, diagnostics, depth + 1, count)?;
 
                     streams.push(Self{
                         header,
                         streams: StreamType::Nested(Box::new(stream)),
-                        time: None
+                        time: None,
+                        location
                     })
                 },
 
+                // Huffman compressed data values. Decoded and
+                // stored with the header for the uncompressed data.
+                b'#' => {
+                    match huffman::decompress(reader, &header, complex.as_deref(), options.max_klv_size) {
+                        Ok((header, values)) => {
+                            *count += values.len();
+                            if *count > options.max_values {
+                                return Err(GpmfError::MaxValuesExceeded{max: options.max_values, got: *count})
+                            }
+                            streams.push(Self{
+                                header,
+                                streams: StreamType::Values(values),
+                                time: None,
+                                location
+                            })
+                        },
+                        // Limits are enforced in lenient mode as well
+                        Err(err @ GpmfError::MaxKlvSizeExceeded{..}) => return Err(err),
+                        Err(err) if options.lenient => {
+                            diagnostics.push(Diagnostic::new(offset, Some(&header.fourcc),
+                                format!("failed to decompress data, stored as invalid: {err}")
+                            ));
+                            reader.seek(SeekFrom::Start(data_start + header.size(false) as u64))?;
+                            streams.push(Self{
+                                header,
+                                streams: StreamType::Values(vec![Value::Invalid]),
+                                time: None,
+                                location
+                            })
+                        },
+                        Err(err) => return Err(err),
+                    }
+                },
+
                 // Anything else will contain data values
                 _ => {
                     let mut values: Vec<Value> = Vec::new();
@@ -116,11 +295,26 @@ impl Stream {
                     // Parse Values in stream.
                     // First check if stream has no data,
                     // to avoid parsing further.
-                    if header.basesize * header.repeats == 0 {
+                    if header.size(false) == 0 {
                         values.push(Value::Empty)
                     } else {
+                        *count += header.repeats as usize;
+                        if *count > options.max_values {
+                            return Err(GpmfError::MaxValuesExceeded{max: options.max_values, got: *count})
+                        }
                         for _ in 0..header.repeats {
-                            values.push(Value::new(reader, &header, complex.as_deref())?);
+                            match Value::new(reader, &header, complex.as_deref()) {
+                                Ok(value) => values.push(value),
+                                Err(err) if options.lenient => {
+                                    diagnostics.push(Diagnostic::new(offset, Some(&header.fourcc),
+                                        format!("failed to read value, stored as invalid: {err}")
+                                    ));
+                                    reader.seek(SeekFrom::Start(data_start + header.size(false) as u64))?;
+                                    values = vec![Value::Invalid];
+                                    break
+                                },
+                                Err(err) => return Err(err),
+                            }
                         }
                     }
 
@@ -134,7 +328,8 @@ impl Stream {
                     streams.push(Self{
                         header,
                         streams: StreamType::Values(values),
-                        time: None
+                        time: None,
+                        location
                     })
                 }
             }
@@ -147,11 +342,136 @@ impl Stream {
         Ok(streams)
     }
 
+    /// Searches for the next `DEVC` header from `start`,
+    /// at 32-bit aligned positions, and returns its position.
+    /// Does not change the reader position.
+    fn resync<R: Read + BufRead + Seek>(
+        reader: &mut R,
+        start: u64,
+        max: u64,
+    ) -> Result<Option<u64>, GpmfError> {
+        let pos = reader.seek(SeekFrom::Current(0))?;
+
+        // GPMF is 32-bit aligned from `start - 4`
+        let mut candidate = start;
+        let mut buf = [0_u8; 8];
+        let mut found = None;
+        while candidate + 8 <= max {
+            reader.seek(SeekFrom::Start(candidate))?;
+            reader.read_exact(&mut buf)?;
+            // DEVC is always a container, i.e. type 0
+            if &buf[..4] == b"DEVC" && buf[4] == 0 {
+                found = Some(candidate);
+                break
+            }
+            candidate += 4;
+        }
+
+        reader.seek(SeekFrom::Start(pos))?;
+        Ok(found)
+    }
+
+    /// Encodes `Stream`s as big endian GPMF bytes. The inverse of `Stream::new()`,
+    /// i.e. `Stream::new()` on the returned bytes will return
+    /// `streams` as they were passed.
+    ///
+    /// Container headers (`DEVC`, `STRM`) are re-calculated if
+    /// the size of their contents has changed, e.g. if values were edited.
+    ///
+    /// Timestamps are derived from the MP4 container and
+    /// are not a part of GPMF, so these are not encoded.
+    pub fn encode(streams: &[Self]) -> Result<Vec<u8>, GpmfError> {
+        let mut buf: Vec<u8> = Vec::new();
+        Self::write_all(streams, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Encodes `Stream` as big endian GPMF bytes.
+    /// See `Stream::encode()`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GpmfError> {
+        Self::encode(std::slice::from_ref(self))
+    }
+
+    /// Writes `Stream`s as big endian GPMF bytes.
+    /// See `Stream::encode()`.
+    pub fn write_all<W: Write>(streams: &[Self], writer: &mut W) -> Result<(), GpmfError> {
+        // Type definitions for complex type, see `Stream::new()`.
+        let mut complex: Option<String> = None;
+
+        for stream in streams.iter() {
+            match &stream.streams {
+                StreamType::Nested(children) => {
+                    let payload = Self::encode(children)?;
+
+                    // Use original header unless content size changed
+                    let header = match stream.header.size(false) as usize == payload.len() {
+                        true => stream.header.to_owned(),
+                        false => Header::nested(stream.fourcc(), payload.len())?,
+                    };
+
+                    header.write(writer)?;
+                    writer.write_all(&payload)?;
+                },
+                StreamType::Values(values) => {
+                    stream.header.write(writer)?;
+
+                    // Streams with no data only contain `Value::Empty`
+                    if stream.header.size(false) > 0 {
+                        if values.len() != stream.header.repeats as usize {
+                            return Err(GpmfError::EncodeError(format!(
+                                "{} has {} values, but header repeat is {}",
+                                stream.fourcc().to_str(),
+                                values.len(),
+                                stream.header.repeats
+                            )))
+                        }
+                        for value in values.iter() {
+                            value.write(writer, &stream.header, complex.as_deref())?;
+                        }
+                    }
+
+                    if stream.has_fourcc(&FourCC::TYPE) {
+                        complex = stream.first_value().and_then(|v| v.into());
+                    }
+
+                    // 0 padding for 32-bit alignment
+                    writer.write_all(&vec![0_u8; stream.header.pad as usize])?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
     /// Set relative timestamp for GPMF stream.
     pub fn set_time(&mut self, time: &Timestamp) {
         self.time = Some(time.to_owned());
     }
 
+    /// Adds `base` to the byte location of current stream
+    /// and all child streams, e.g. the offset of an MP4 sample
+    /// for streams parsed from that sample.
+    pub fn offset_location(&mut self, base: u64) {
+        if let Some(location) = self.location.as_mut() {
+            location.offset += base;
+        }
+        if let StreamType::Nested(streams) = &mut self.streams {
+            streams.iter_mut()
+                .for_each(|s| s.offset_location(base));
+        }
+    }
+
+    /// Removes byte location for current stream
+    /// and all child streams, for sources where
+    /// the absolute position is not known.
+    pub(crate) fn clear_location(&mut self) {
+        self.location = None;
+        if let StreamType::Nested(streams) = &mut self.streams {
+            streams.iter_mut()
+                .for_each(|s| s.clear_location());
+        }
+    }
+
     /// Set relative timestamp for GPMF stream.
     pub fn with_time(self, time: &Timestamp) -> Self {
         Self {
@@ -540,3 +860,180 @@ impl Stream {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Stream, StreamType};
+    use crate::GpmfError;
+    use crate::gpmf::{FourCC, Header, ParseOptions, Value};
+    use crate::tests::{klv, klv_container};
+
+    fn parse(bytes: &[u8]) -> Vec<Stream> {
+        Stream::new(&mut Cursor::new(bytes), bytes.len()).unwrap()
+    }
+
+    /// Asserts that encoding parsed `bytes` returns the same bytes,
+    /// and that parsing the encoded bytes returns the same streams.
+    fn assert_roundtrip(bytes: &[u8]) -> Vec<Stream> {
+        let streams = parse(bytes);
+        let encoded = Stream::encode(&streams).unwrap();
+        assert_eq!(encoded, bytes);
+        assert_eq!(parse(&encoded), streams);
+        streams
+    }
+
+    #[test]
+    fn roundtrip_padding() {
+        let bytes = [
+            klv(b"SCAL", b's', 2, 1, &10_i16.to_be_bytes()),
+            klv(b"ORIN", b'c', 3, 1, b"ZXY"),
+            klv(b"TMPC", b'f', 4, 1, &36.5_f32.to_be_bytes()),
+        ].concat();
+
+        let streams = assert_roundtrip(&bytes);
+
+        assert_eq!(streams.iter().map(|s| s.header.pad).collect::<Vec<_>>(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn roundtrip_strings() {
+        // Trailing nulls are removed when parsed and restored when encoded
+        let bytes = klv(b"STNM", b'c', 8, 1, b"Accel\0\0\0");
+        let streams = assert_roundtrip(&bytes);
+        assert_eq!(streams[0].first_value(), Some(&Value::String("Accel".to_owned())));
+
+        // Structure size 1 strings (older devices) are written
+        // with size and repeat switched, but parse to the same stream
+        let bytes = klv(b"STNM", b'c', 1, 4, b"Gyro");
+        let streams = parse(&bytes);
+        let encoded = Stream::encode(&streams).unwrap();
+        assert_eq!(encoded, klv(b"STNM", b'c', 4, 1, b"Gyro"));
+        assert_eq!(parse(&encoded), streams);
+    }
+
+    #[test]
+    fn roundtrip_complex() {
+        let mut data = Vec::new();
+        for (n, f) in [(1_u32, 1.5_f32), (2, -0.5)] {
+            data.extend(n.to_be_bytes());
+            data.extend(f.to_be_bytes());
+        }
+        let bytes = [
+            klv(b"TYPE", b'c', 2, 1, b"Lf"),
+            klv(b"CPLX", b'?', 8, 2, &data),
+        ].concat();
+
+        let streams = assert_roundtrip(&bytes);
+
+        assert_eq!(streams[1].first_value(), Some(&Value::Complex(vec![
+            Box::new(Value::Uint32(vec![1])),
+            Box::new(Value::Float32(vec![1.5])),
+        ])));
+    }
+
+    #[test]
+    fn roundtrip_nested() {
+        let gyro: Vec<u8> = [1_i16, 2, 3, -1, -2, -3].iter().flat_map(|n| n.to_be_bytes()).collect();
+        let strm = klv_container(b"STRM", &[
+            klv(b"STNM", b'c', 4, 1, b"Gyro"),
+            klv(b"SCAL", b's', 2, 1, &100_i16.to_be_bytes()),
+            klv(b"GYRO", b's', 6, 2, &gyro),
+        ]);
+        let bytes = klv_container(b"DEVC", &[
+            klv(b"DVID", b'L', 4, 1, &1_u32.to_be_bytes()),
+            klv(b"DVNM", b'c', 6, 1, b"Camera"),
+            strm,
+        ]);
+
+        let streams = assert_roundtrip(&bytes);
+
+        assert_eq!(streams.len(), 1);
+        assert_eq!(
+            streams[0].find(&FourCC::STRM)
+                .and_then(|s| s.find(&FourCC::GYRO))
+                .and_then(|s| s.last_value()),
+            Some(&Value::Sint16(vec![-1, -2, -3]))
+        );
+    }
+
+    #[test]
+    fn roundtrip_large_container() {
+        // 12 000 samples * 6 bytes exceed u16::MAX,
+        // container size is instead set as structure size 4
+        let gyro = Stream {
+            header: Header::from_parts(FourCC::GYRO, b's', 6, 12_000),
+            streams: StreamType::Values((0..12_000).map(|n| Value::Sint16(vec![n as i16, 0, -1])).collect()),
+            time: None,
+            location: None,
+        };
+        // Header with outdated size, re-calculated when encoded
+        let devc = Stream {
+            header: Header::from_parts(FourCC::DEVC, 0, 1, 0),
+            streams: StreamType::Nested(Box::new(vec![gyro])),
+            time: None,
+            location: None,
+        };
+
+        let encoded = devc.to_bytes().unwrap();
+        let len = encoded.len() - 8;
+        assert!(len > u16::MAX as usize);
+        assert_eq!(&encoded[4..8], [0, 4, ((len / 4) >> 8) as u8, (len / 4) as u8]);
+
+        let mut streams = parse(&encoded);
+        streams.iter_mut().for_each(|s| s.clear_location());
+        assert_eq!(streams, vec![Stream {
+            header: Header::nested(&FourCC::DEVC, len).unwrap(),
+            ..devc
+        }]);
+    }
+
+    fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<Vec<Stream>, GpmfError> {
+        Stream::new_with_options(&mut Cursor::new(bytes), bytes.len(), options, &mut Vec::new())
+    }
+
+    #[test]
+    fn limit_depth() {
+        // DEVC > STRM > STRM > SCAL, values at depth 3
+        let scal = klv(b"SCAL", b's', 2, 1, &10_i16.to_be_bytes());
+        let bytes = klv_container(b"DEVC", &[klv_container(b"STRM", &[klv_container(b"STRM", &[scal])])]);
+
+        let options = ParseOptions { max_depth: 3, ..ParseOptions::default() };
+        assert!(parse_with(&bytes, &options).is_ok());
+
+        let options = ParseOptions { max_depth: 2, ..ParseOptions::default() };
+        assert!(matches!(parse_with(&bytes, &options), Err(GpmfError::RecurseDepthExceeded((3, 2)))));
+    }
+
+    #[test]
+    fn limit_values() {
+        let gyro: Vec<u8> = [1_i16, 2, 3, -1, -2, -3].iter().flat_map(|n| n.to_be_bytes()).collect();
+        let bytes = [
+            klv(b"SCAL", b's', 2, 1, &100_i16.to_be_bytes()),
+            klv(b"GYRO", b's', 6, 2, &gyro),
+        ].concat();
+
+        let options = ParseOptions { max_values: 3, ..ParseOptions::default() };
+        assert!(parse_with(&bytes, &options).is_ok());
+
+        let options = ParseOptions { max_values: 2, ..ParseOptions::default() };
+        assert!(matches!(parse_with(&bytes, &options), Err(GpmfError::MaxValuesExceeded{max: 2, got: 3})));
+
+        // Enforced in lenient mode as well
+        let options = ParseOptions { max_values: 2, ..ParseOptions::lenient() };
+        assert!(matches!(parse_with(&bytes, &options), Err(GpmfError::MaxValuesExceeded{max: 2, got: 3})));
+    }
+
+    #[test]
+    fn limit_klv_size() {
+        let gyro: Vec<u8> = [1_i16, 2, 3, -1, -2, -3].iter().flat_map(|n| n.to_be_bytes()).collect();
+        let bytes = klv(b"GYRO", b's', 6, 2, &gyro);
+
+        let options = ParseOptions { max_klv_size: 12, ..ParseOptions::default() };
+        assert!(parse_with(&bytes, &options).is_ok());
+
+        let options = ParseOptions { max_klv_size: 11, ..ParseOptions::default() };
+        assert!(matches!(parse_with(&bytes, &options), Err(GpmfError::MaxKlvSizeExceeded{max: 11, got: 12})));
+    }
+}
@@ -0,0 +1,501 @@
+//! Borrowed, lazily parsed view over raw GPMF bytes.
+//!
+//! `Stream::new()` parses all values into owned `Stream`s up front,
+//! which is memory intensive for long recordings.
+//! `StreamRef` instead walks the KLV headers of a byte slice,
+//! e.g. a single `DEVC` sample (`mp4iter::Sample::raw()`),
+//! or a memory mapped, extracted GPMF file,
+//! and only decodes values when these are accessed.
+//!
+//! ```rs
+//! use gpmf_rs::{DataType, FourCC, gpmf::StreamRef};
+//!
+//! let bytes = std::fs::read("GOPRO_VIDEO.gpmf")?;
+//! for devc in StreamRef::from_slice(&bytes) {
+//!     for strm in devc.filter(&DataType::Gyroscope) {
+//!         let gyro = strm.find(&FourCC::GYRO).and_then(|s| s.to_vec_f64());
+//!     }
+//! }
+//! ```
+
+use std::{io::Cursor, slice::ChunksExact};
+
+use crate::{DataType, GpmfError};
+use super::{FourCC, Header, ParseOptions, Stream, StreamType, Value, huffman};
+
+/// GPMF KLV header size in bytes
+/// (Four CC, type, size, repeats).
+const HEADER_SIZE: usize = 8;
+
+/// Data size in bytes for a raw GPMF header, excluding padding.
+fn data_len(header: &[u8]) -> usize {
+    header[5] as usize * u16::from_be_bytes([header[6], header[7]]) as usize
+}
+
+/// Complex type definition, stored as raw bytes (ISO8859-1).
+fn complextype_str(types: Option<&[u8]>) -> Option<String> {
+    types.map(|t| t.iter()
+        .filter(|c| **c != 0)
+        .map(|c| *c as char)
+        .collect()
+    )
+}
+
+/// Borrowed GPMF stream. Holds the raw header and
+/// data, which are only parsed on access.
+/// Cheap to copy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamRef<'a> {
+    /// Raw 8 byte header.
+    header: &'a [u8],
+    /// Raw data, excluding padding.
+    data: &'a [u8],
+    /// Raw complex type definition (`TYPE`)
+    /// preceding current stream, if any.
+    complextype: Option<&'a [u8]>,
+}
+
+impl<'a> StreamRef<'a> {
+    /// Returns an iterator over top-level streams (usually `DEVC`)
+    /// in raw GPMF bytes, e.g. an MP4 sample or an extracted GPMF file.
+    ///
+    /// Iteration ends at zero padding
+    /// (as in GPMF in the MP4 `udta` atom),
+    /// or if a stream is truncated.
+    pub fn from_slice(data: &'a [u8]) -> StreamRefIter<'a> {
+        StreamRefIter::new(data)
+    }
+
+    /// Returns the parsed header.
+    pub fn header(&self) -> Header {
+        Header::from_parts(
+            self.fourcc(),
+            self.header[4],
+            self.header[5],
+            u16::from_be_bytes([self.header[6], self.header[7]])
+        )
+    }
+
+    /// Returns Four CC for stream.
+    pub fn fourcc(&self) -> FourCC {
+        FourCC::from_slice(&self.header[..4])
+    }
+
+    /// Returns `true` if specified Four CC
+    /// matches that of current stream.
+    /// Compares raw bytes, without parsing the header.
+    pub fn has_fourcc(&self, fourcc: &FourCC) -> bool {
+        self.header[..4] == fourcc.to_bytes()
+    }
+
+    /// Returns GPMF basetype.
+    pub fn basetype(&self) -> u8 {
+        self.header[4]
+    }
+
+    /// Returns `true` if stream is a container,
+    /// e.g. `DEVC`, `STRM`.
+    pub fn is_nested(&self) -> bool {
+        self.basetype() == 0
+    }
+
+    /// Returns raw data, excluding header and padding.
+    pub fn raw(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns original size in bytes for current stream, excluding header.
+    /// `aligned = true` returns the size including 32-bit alignment padding.
+    pub fn size(&self, aligned: bool) -> u32 {
+        let size = self.data.len() as u32;
+        match aligned {
+            true => size + (4 - size % 4) % 4,
+            false => size,
+        }
+    }
+
+    /// Returns `(basesize, repeats)` for values,
+    /// where strings with size 1 are read as a single value.
+    fn layout(&self) -> (usize, usize) {
+        let basesize = self.header[5] as usize;
+        let repeats = u16::from_be_bytes([self.header[6], self.header[7]]) as usize;
+        match (self.basetype(), basesize) {
+            (b'c', 1) => (repeats, 1),
+            _ => (basesize, repeats),
+        }
+    }
+
+    /// Returns an iterator over child streams.
+    /// Empty if current stream contains values.
+    pub fn streams(&self) -> StreamRefIter<'a> {
+        match self.is_nested() {
+            true => StreamRefIter::new(self.data),
+            false => StreamRefIter::new(&[]),
+        }
+    }
+
+    /// Returns an iterator over values.
+    /// Empty if current stream is a container.
+    ///
+    /// For compressed streams (basetype `#`) the compressed data
+    /// is returned as is. Use `StreamRef::to_vec_f64()`
+    /// or `StreamRef::to_stream()` to decode these.
+    pub fn values(&self) -> ValueRefIter<'a> {
+        let (basesize, _) = self.layout();
+        let chunks = match self.is_nested() || basesize == 0 {
+            true => (&[] as &[u8]).chunks_exact(1),
+            false => self.data.chunks_exact(basesize),
+        };
+        ValueRefIter {
+            chunks,
+            basetype: self.basetype(),
+            complextype: self.complextype,
+        }
+    }
+
+    /// Returns first value in a terminal stream (i.e. contains values).
+    pub fn first_value(&self) -> Option<ValueRef<'a>> {
+        self.values().next()
+    }
+
+    /// Find first stream with specified FourCC.
+    /// Matches self and direct decendants.
+    pub fn find(&self, fourcc: &FourCC) -> Option<StreamRef<'a>> {
+        if self.has_fourcc(fourcc) {
+            return Some(*self)
+        }
+        self.streams().find(|s| s.has_fourcc(fourcc))
+    }
+
+    /// Find all direct decendants with specified FourCC.
+    pub fn find_all(&self, fourcc: &FourCC) -> impl Iterator<Item = StreamRef<'a>> + 'a {
+        let fourcc = fourcc.to_bytes();
+        self.streams()
+            .filter(move |s| s.header[..4] == fourcc)
+    }
+
+    /// Returns the human redable name of the stream
+    /// if it is a `STRM`, (stored as string in `STNM`),
+    /// otherwise `None` is returned.
+    pub fn name(&self) -> Option<String> {
+        let value = self.find(&FourCC::STNM)?
+            .first_value()?
+            .decode()
+            .ok()?;
+        (&value).into()
+    }
+
+    /// Find all direct decendants with specified `DataType`.
+    pub fn filter(&self, content_type: &DataType) -> impl Iterator<Item = StreamRef<'a>> + 'a {
+        let name = content_type.to_str().to_owned();
+        self.streams()
+            .filter(move |s| s.name().as_deref() == Some(name.as_str()))
+    }
+
+    /// Decodes numerical values in a terminal stream and casts these as `Vec<Vec<f64>>`,
+    /// where each "inner" `Vec<f64>` represents the values wrapped by a single value.
+    /// Compressed streams are decompressed first.
+    ///
+    /// `None` is returned if the stream is not a terminal node, or if
+    /// values are not numerical.
+    pub fn to_vec_f64(&self) -> Option<Vec<Vec<f64>>> {
+        match self.basetype() {
+            0 => None,
+            b'#' => {
+                let types = complextype_str(self.complextype);
+                let (_, values) = huffman::decompress(
+                    &mut Cursor::new(self.data),
+                    &self.header(),
+                    types.as_deref(),
+                    ParseOptions::default().max_klv_size
+                ).ok()?;
+                values.iter()
+                    .map(|v| v.into())
+                    .collect()
+            },
+            _ => self.values()
+                .map(|v| v.to_f64())
+                .collect()
+        }
+    }
+
+    /// Decodes numerical values in a terminal stream and casts these as `Vec<f64>`.
+    ///
+    /// For cases where each value is known to only wrap a single number.
+    pub fn to_f64(&self) -> Option<Vec<f64>> {
+        self.to_vec_f64()?
+            .into_iter()
+            .map(|v| v.first().copied())
+            .collect()
+    }
+
+    /// Decodes current stream, and any child streams,
+    /// into an owned `Stream`. Equivalent to
+    /// `Stream::new()` for the same bytes.
+    pub fn to_stream(&self) -> Result<Stream, GpmfError> {
+        let header = self.header();
+        let (header, streams) = match self.basetype() {
+            0 => {
+                let streams = self.streams()
+                    .map(|s| s.to_stream())
+                    .collect::<Result<Vec<_>, _>>()?;
+                (header, StreamType::Nested(Box::new(streams)))
+            },
+            b'#' => {
+                let types = complextype_str(self.complextype);
+                let (header, values) = huffman::decompress(
+                    &mut Cursor::new(self.data),
+                    &header,
+                    types.as_deref(),
+                    ParseOptions::default().max_klv_size
+                )?;
+                (header, StreamType::Values(values))
+            },
+            _ if self.data.is_empty() => (header, StreamType::Values(vec![Value::Empty])),
+            _ => {
+                let values = self.values()
+                    .map(|v| v.decode())
+                    .collect::<Result<Vec<_>, _>>()?;
+                (header, StreamType::Values(values))
+            }
+        };
+
+        Ok(Stream {
+            header,
+            streams,
+            time: None,
+            location: None
+        })
+    }
+}
+
+/// Iterator over borrowed GPMF streams on the same level.
+/// Keeps track of complex type definitions (`TYPE`).
+#[derive(Debug, Clone)]
+pub struct StreamRefIter<'a> {
+    data: &'a [u8],
+    pos: usize,
+    complextype: Option<&'a [u8]>,
+}
+
+impl<'a> StreamRefIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            complextype: None,
+        }
+    }
+
+    /// Byte offset for the next stream,
+    /// relative to the start of the iterated slice.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl<'a> Iterator for StreamRefIter<'a> {
+    type Item = StreamRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.data.get(self.pos..self.pos + HEADER_SIZE)?;
+
+        // Zero padding, so far specific to GPMF in MP4 `udta` atoms.
+        if header[..4] == [0, 0, 0, 0] {
+            self.pos = self.data.len();
+            return None
+        }
+
+        let start = self.pos + HEADER_SIZE;
+        let end = start + data_len(header);
+        let Some(data) = self.data.get(start..end) else {
+            // Truncated stream
+            self.pos = self.data.len();
+            return None
+        };
+
+        // Skip 32-bit alignment padding
+        self.pos = end + (4 - data_len(header) % 4) % 4;
+
+        let stream = StreamRef {
+            header,
+            data,
+            complextype: self.complextype,
+        };
+
+        // Complex type definitions apply to following streams
+        if stream.has_fourcc(&FourCC::TYPE) {
+            self.complextype = Some(data);
+        }
+
+        Some(stream)
+    }
+}
+
+/// Borrowed, raw GPMF value, corresponding to a single `Value`.
+/// Decoded on access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueRef<'a> {
+    basetype: u8,
+    /// Raw big endian bytes.
+    data: &'a [u8],
+    /// Raw complex type definition, if any.
+    complextype: Option<&'a [u8]>,
+}
+
+impl<'a> ValueRef<'a> {
+    /// Returns GPMF basetype.
+    pub fn basetype(&self) -> u8 {
+        self.basetype
+    }
+
+    /// Returns raw big endian bytes.
+    pub fn raw(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Decodes into an owned `Value`.
+    pub fn decode(&self) -> Result<Value, GpmfError> {
+        let header = Header {
+            fourcc: FourCC::default(),
+            basetype: self.basetype,
+            basesize: self.data.len() as u16,
+            repeats: 1,
+            pad: 0,
+        };
+        let types = complextype_str(self.complextype);
+        Value::new(&mut Cursor::new(self.data), &header, types.as_deref())
+    }
+
+    /// Decodes numerical values and casts these as `Vec<f64>`.
+    /// Plain numerical types are converted directly
+    /// from the raw bytes, without creating a `Value`.
+    ///
+    /// `None` is returned if the value is not numerical.
+    pub fn to_f64(&self) -> Option<Vec<f64>> {
+        match self.basetype {
+            b'b' => convert(self.data, |b: [u8; 1]| f64::from(i8::from_be_bytes(b))),
+            b'B' => convert(self.data, |b: [u8; 1]| f64::from(u8::from_be_bytes(b))),
+            b's' => convert(self.data, |b: [u8; 2]| f64::from(i16::from_be_bytes(b))),
+            b'S' => convert(self.data, |b: [u8; 2]| f64::from(u16::from_be_bytes(b))),
+            b'l' => convert(self.data, |b: [u8; 4]| f64::from(i32::from_be_bytes(b))),
+            b'L' => convert(self.data, |b: [u8; 4]| f64::from(u32::from_be_bytes(b))),
+            b'f' => convert(self.data, |b: [u8; 4]| f64::from(f32::from_be_bytes(b))),
+            b'd' => convert(self.data, f64::from_be_bytes),
+            b'j' => convert(self.data, |b: [u8; 8]| i64::from_be_bytes(b) as f64),
+            b'J' => convert(self.data, |b: [u8; 8]| u64::from_be_bytes(b) as f64),
+            // Q15.16 format -> div by 2^15
+            b'q' => convert(self.data, |b: [u8; 4]| u32::from_be_bytes(b) as f64 / (2_u16).pow(15) as f64),
+            // Q31.32 format -> div by 2^31
+            b'Q' => convert(self.data, |b: [u8; 8]| u64::from_be_bytes(b) as f64 / (2_u32).pow(31) as f64),
+            // Complex types
+            _ => {
+                let value = self.decode().ok()?;
+                (&value).into()
+            }
+        }
+    }
+}
+
+/// Converts raw big endian bytes to `f64`,
+/// `N` bytes at a time.
+fn convert<const N: usize>(data: &[u8], f: impl Fn([u8; N]) -> f64) -> Option<Vec<f64>> {
+    data.chunks_exact(N)
+        .map(|c| c.try_into().ok().map(&f))
+        .collect()
+}
+
+/// Iterator over borrowed values in a terminal stream.
+#[derive(Debug, Clone)]
+pub struct ValueRefIter<'a> {
+    chunks: ChunksExact<'a, u8>,
+    basetype: u8,
+    complextype: Option<&'a [u8]>,
+}
+
+impl<'a> Iterator for ValueRefIter<'a> {
+    type Item = ValueRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chunks.next()
+            .map(|data| ValueRef {
+                basetype: self.basetype,
+                data,
+                complextype: self.complextype,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::StreamRef;
+    use crate::gpmf::{FourCC, Stream};
+    use crate::tests::{klv, klv_container};
+
+    fn devc(name: &[u8; 6], gyro: [i16; 6]) -> Vec<u8> {
+        let gyro: Vec<u8> = gyro.iter().flat_map(|n| n.to_be_bytes()).collect();
+        let mut cplx = Vec::new();
+        for (n, f) in [(1_u32, 1.5_f32), (2, -0.5)] {
+            cplx.extend(n.to_be_bytes());
+            cplx.extend(f.to_be_bytes());
+        }
+        klv_container(b"DEVC", &[
+            klv(b"DVID", b'L', 4, 1, &1_u32.to_be_bytes()),
+            klv(b"DVNM", b'c', 6, 1, name),
+            klv_container(b"STRM", &[
+                // Structure size 1 string, size and repeats switched
+                klv(b"STNM", b'c', 1, 4, b"Gyro"),
+                klv(b"SCAL", b's', 2, 1, &100_i16.to_be_bytes()),
+                klv(b"EMPT", b'L', 4, 0, &[]),
+                klv(b"GYRO", b's', 6, 2, &gyro),
+            ]),
+            klv_container(b"STRM", &[
+                klv(b"STNM", b'c', 7, 1, b"Complex"),
+                klv(b"TYPE", b'c', 2, 1, b"Lf"),
+                klv(b"CPLX", b'?', 8, 2, &cplx),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn stream_ref_equivalence() {
+        // Trailing zero padding as in MP4 udta atoms
+        let bytes = [
+            devc(b"Camera", [1, 2, 3, -1, -2, -3]),
+            devc(b"Drone\0", [4, 5, 6, -4, -5, -6]),
+            vec![0; 8],
+        ].concat();
+
+        let mut streams = Stream::new(&mut Cursor::new(&bytes), bytes.len()).unwrap();
+        streams.iter_mut().for_each(|s| s.clear_location());
+
+        let refs: Vec<StreamRef> = StreamRef::from_slice(&bytes).collect();
+        let decoded = refs.iter()
+            .map(|s| s.to_stream())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(refs.len(), 2);
+        assert_eq!(decoded, streams);
+
+        for (stream_ref, stream) in refs.iter().zip(streams.iter()) {
+            assert_eq!(stream_ref.header(), stream.header);
+            assert_eq!(stream_ref.size(true), stream.size(true));
+
+            let strm_refs: Vec<StreamRef> = stream_ref.find_all(&FourCC::STRM).collect();
+            let strms: Vec<Stream> = stream.find_all(&FourCC::STRM);
+            assert_eq!(strm_refs.len(), 2);
+            assert_eq!(strm_refs.len(), strms.len());
+
+            for (strm_ref, strm) in strm_refs.iter().zip(strms.iter()) {
+                assert_eq!(strm_ref.name(), strm.name());
+                assert_eq!(
+                    strm_ref.streams().last().and_then(|s| s.to_vec_f64()),
+                    strm.last_stream().and_then(|s| s.to_vec_f64())
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Mapping from relative time (MP4/`Timestamp`, i.e. time since video start)
+//! to absolute UTC time.
+//!
+//! `GPSU` (`GPS5` devices) and per-point datetimes (`GPS9` devices)
+//! for points with a satellite lock are fitted against their
+//! relative timestamps using least squares. This corrects for
+//! camera clock drift over long recordings, rather than
+//! relying on a single point. If no points with a satellite lock are
+//! logged, the MP4 creation time is used as start time,
+//! which depends on the camera clock being set correctly.
+//!
+//! ```rs
+//! use gpmf_rs::{FourCC, Gpmf};
+//! use std::path::Path;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! let model = gpmf.time_model()?;
+//! println!("{:?} drift: {} RMS: {:?}s", model.source, model.scale, model.rms());
+//! for accl in gpmf.timed_samples(&FourCC::ACCL) {
+//!     for (utc, sample) in model.utc_samples(&accl) {
+//!         println!("{utc} {:?}", sample.values);
+//!     }
+//! }
+//! ```
+
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+
+use crate::{GpmfError, Gps};
+use super::{TimedSample, TimedSamples};
+
+/// Source for absolute time in a `TimeModel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeSource {
+    /// Fitted from GPS datetimes.
+    Gps,
+    /// MP4 creation time. No drift correction.
+    CreationTime,
+}
+
+/// Linear mapping from relative time to UTC:
+/// `utc = origin + scale * relative`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeModel {
+    /// UTC at relative time zero (video start).
+    pub origin: OffsetDateTime,
+    /// Elapsed GPS time per elapsed MP4 time.
+    /// `1.0` means no clock drift.
+    pub scale: f64,
+    pub source: TimeSource,
+    /// Relative time and residual (GPS time minus fitted time)
+    /// in seconds for each point used for fitting.
+    /// Empty if source is `TimeSource::CreationTime`.
+    pub residuals: Vec<(Duration, f64)>,
+}
+
+impl TimeModel {
+    /// Fits time model to GPS points with a satellite lock
+    /// of at least `min_fix` (defaults to 2, i.e. 2D lock).
+    /// Falls back to `creation_time` if no such points exist.
+    ///
+    /// Raises `GpmfError::NoData` if neither is available.
+    pub fn new(
+        gps: &Gps,
+        min_fix: Option<u32>,
+        creation_time: Option<PrimitiveDateTime>
+    ) -> Result<Self, GpmfError> {
+        match Self::from_gps(gps, min_fix) {
+            Some(model) => Ok(model),
+            None => creation_time
+                .map(Self::from_creation_time)
+                .ok_or(GpmfError::NoData),
+        }
+    }
+
+    /// Fits time model to GPS points with a satellite lock
+    /// of at least `min_fix` (defaults to 2, i.e. 2D lock).
+    /// Returns `None` if no such points exist.
+    pub fn from_gps(gps: &Gps, min_fix: Option<u32>) -> Option<Self> {
+        let points: Vec<(Duration, OffsetDateTime)> = gps.iter()
+            .filter(|p| p.fix >= min_fix.unwrap_or(2))
+            .filter_map(|p| Some((p.timestamp, p.datetime().ok()?)))
+            .collect();
+
+        // Fit against seconds since first point to preserve precision
+        let reference = points.first()?.1;
+        let xy: Vec<(f64, f64)> = points.iter()
+            .map(|(t, dt)| (t.as_seconds_f64(), (*dt - reference).as_seconds_f64()))
+            .collect();
+
+        let n = xy.len() as f64;
+        let mean_x = xy.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = xy.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let (sxy, sxx) = xy.iter()
+            .fold((0., 0.), |(sxy, sxx), (x, y)| (
+                sxy + (x - mean_x) * (y - mean_y),
+                sxx + (x - mean_x).powi(2)
+            ));
+
+        // Single point, all points at the same relative time,
+        // or GPS time not advancing with relative time
+        // (e.g. cached GPSU repeated across DEVC streams)
+        let scale = match sxx > 0. && sxy / sxx > 0. && (sxy / sxx).is_finite() {
+            true => sxy / sxx,
+            false => 1.,
+        };
+        let intercept = mean_y - scale * mean_x;
+
+        let residuals = points.iter()
+            .zip(xy.iter())
+            .map(|((t, _), (x, y))| (*t, y - (intercept + scale * x)))
+            .collect();
+
+        Some(Self {
+            origin: reference + Duration::seconds_f64(intercept),
+            scale,
+            source: TimeSource::Gps,
+            residuals,
+        })
+    }
+
+    /// Time model with MP4 creation time as start time.
+    /// GoPro does not log time zone, so creation time
+    /// is assumed to be UTC.
+    pub fn from_creation_time(creation_time: PrimitiveDateTime) -> Self {
+        Self {
+            origin: creation_time.assume_utc(),
+            scale: 1.,
+            source: TimeSource::CreationTime,
+            residuals: Vec::new(),
+        }
+    }
+
+    /// Returns UTC for time relative to video start.
+    pub fn utc(&self, relative: Duration) -> OffsetDateTime {
+        self.origin + Duration::seconds_f64(relative.as_seconds_f64() * self.scale)
+    }
+
+    /// Returns time relative to video start for UTC.
+    pub fn relative(&self, utc: OffsetDateTime) -> Duration {
+        Duration::seconds_f64((utc - self.origin).as_seconds_f64() / self.scale)
+    }
+
+    /// Returns UTC for each sample.
+    pub fn utc_samples<'a>(&self, samples: &'a TimedSamples) -> impl Iterator<Item = (OffsetDateTime, &'a TimedSample)> {
+        let (origin, scale) = (self.origin, self.scale);
+        samples.iter()
+            .map(move |s| (origin + Duration::seconds_f64(s.time.as_seconds_f64() * scale), s))
+    }
+
+    /// Returns root mean square of residuals in seconds.
+    pub fn rms(&self) -> Option<f64> {
+        if self.residuals.is_empty() {
+            return None
+        }
+        let sum = self.residuals.iter()
+            .map(|(_, r)| r.powi(2))
+            .sum::<f64>();
+        Some((sum / self.residuals.len() as f64).sqrt())
+    }
+
+    /// Returns largest absolute residual in seconds.
+    pub fn max_residual(&self) -> Option<f64> {
+        self.residuals.iter()
+            .map(|(_, r)| r.abs())
+            .reduce(f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, OffsetDateTime, macros::datetime};
+
+    use super::{TimeModel, TimeSource};
+    use crate::{GoProPoint, Gps, GpmfError};
+
+    const ORIGIN: OffsetDateTime = datetime!(2020-01-01 12:00:00 UTC);
+
+    /// Point with 3D lock at `relative` seconds, logged at `utc`.
+    fn point(relative: f64, utc: OffsetDateTime) -> GoProPoint {
+        let mut p = GoProPoint {
+            fix: 3,
+            timestamp: Duration::seconds_f64(relative),
+            ..GoProPoint::default()
+        };
+        p.add_duration(utc - GoProPoint::datetime_default());
+        p
+    }
+
+    /// Points from 10 to 109 seconds, with clock drift `scale`
+    /// and `noise(i)` seconds added to GPS time.
+    fn track(scale: f64, noise: impl Fn(usize) -> f64) -> Gps {
+        Gps::new((0..100)
+            .map(|i| {
+                let t = 10. + i as f64;
+                point(t, ORIGIN + Duration::seconds_f64(t * scale + noise(i)))
+            })
+            .collect())
+    }
+
+    fn seconds_between(a: OffsetDateTime, b: OffsetDateTime) -> f64 {
+        (a - b).as_seconds_f64().abs()
+    }
+
+    #[test]
+    fn time_model_linear() {
+        let model = TimeModel::from_gps(&track(1.0001, |_| 0.), None).unwrap();
+
+        assert_eq!(model.source, TimeSource::Gps);
+        assert!((model.scale - 1.0001).abs() < 1e-9, "{}", model.scale);
+        assert!(seconds_between(model.origin, ORIGIN) < 1e-6);
+        assert!(model.max_residual().unwrap() < 1e-6);
+        let utc = model.utc(Duration::seconds(50));
+        assert!(seconds_between(utc, ORIGIN + Duration::seconds_f64(50.005)) < 1e-6);
+        assert!((model.relative(utc) - Duration::seconds(50)).abs() < Duration::microseconds(1));
+    }
+
+    #[test]
+    fn time_model_noisy() {
+        // Alternating +/- 20 ms
+        let noise = |i: usize| if i % 2 == 0 { 0.02 } else { -0.02 };
+        let model = TimeModel::from_gps(&track(1., noise), None).unwrap();
+
+        assert!((model.scale - 1.).abs() < 1e-4, "{}", model.scale);
+        assert!(seconds_between(model.origin, ORIGIN) < 0.01);
+        assert!((model.rms().unwrap() - 0.02).abs() < 0.001);
+        assert!(model.max_residual().unwrap() < 0.03);
+    }
+
+    #[test]
+    fn time_model_degenerate() {
+        // Single point
+        let utc = ORIGIN + Duration::seconds(20);
+        let model = TimeModel::from_gps(&Gps::new(vec![point(5., utc)]), None).unwrap();
+        assert_eq!(model.scale, 1.);
+        assert_eq!(model.origin, ORIGIN + Duration::seconds(15));
+        assert_eq!(model.rms(), Some(0.));
+
+        // Same relative time
+        let gps = Gps::new(vec![point(5., utc), point(5., utc + Duration::seconds(1))]);
+        let model = TimeModel::from_gps(&gps, None).unwrap();
+        assert_eq!(model.scale, 1.);
+        assert_eq!(model.origin, ORIGIN + Duration::seconds_f64(15.5));
+
+        // GPS time not advancing
+        let gps = Gps::new(vec![point(5., utc), point(6., utc), point(7., utc)]);
+        assert_eq!(TimeModel::from_gps(&gps, None).unwrap().scale, 1.);
+    }
+
+    #[test]
+    fn time_model_fallback() {
+        let mut unlocked = point(1., ORIGIN);
+        unlocked.fix = 0;
+        let gps = Gps::new(vec![unlocked]);
+
+        assert!(TimeModel::from_gps(&gps, None).is_none());
+        let model = TimeModel::new(&gps, None, Some(datetime!(2020-01-01 12:00:00))).unwrap();
+        assert_eq!(model.source, TimeSource::CreationTime);
+        assert_eq!(model.origin, ORIGIN);
+        assert_eq!(model.rms(), None);
+        assert!(matches!(TimeModel::new(&gps, None, None), Err(GpmfError::NoData)));
+        assert!(matches!(TimeModel::new(&Gps::default(), None, None), Err(GpmfError::NoData)));
+    }
+}
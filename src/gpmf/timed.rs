@@ -0,0 +1,456 @@
+//! Per-sample timestamps for GPMF data streams.
+//!
+//! MP4 timing only covers the `DEVC` level (see `Timestamp`),
+//! i.e. roughly one second of data. Individual samples are timed
+//! in the same manner as GoPro's `gpmf-parser`:
+//! - `TSMP` (total samples delivered) across all payloads is fitted
+//!   against payload time to derive the actual sample rate,
+//!   and the time of the first sample.
+//! - `STMP` (microsecond timestamp for the first sample in a payload)
+//!   is used as payload start if present.
+//! - `TIMO` (time offset, data delayed by `x` seconds) is subtracted.
+//!
+//! If none of these are logged, samples are spread evenly across
+//! the `DEVC` duration.
+//!
+//! ```rs
+//! use gpmf_rs::{Gpmf, FourCC};
+//! use std::path::Path;
+//!
+//! let gpmf = Gpmf::new(&Path::new("GOPRO_VIDEO.MP4"))?;
+//! for gyro in gpmf.timed_samples(&FourCC::GYRO) {
+//!     println!("{:?} {:?} Hz", gyro.device, gyro.rate);
+//!     for sample in gyro.iter() {
+//!         println!("{:.4} {:?}", sample.time.as_seconds_f64(), sample.values);
+//!     }
+//! }
+//! ```
+
+use std::ops::Range;
+
+use time::Duration;
+
+use crate::DataType;
+use super::{FourCC, Stream, StreamType};
+
+/// Single sample with its time relative to video start.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimedSample {
+    /// Time relative to video start.
+    pub time: Duration,
+    /// Sample values, with `SCAL` applied.
+    pub values: Vec<f64>,
+}
+
+/// All samples for a single data stream (e.g. `GYRO`) and device,
+/// each with its own timestamp.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimedSamples {
+    /// Device name (`DVNM`).
+    pub device: Option<String>,
+    /// Four CC for data stream.
+    pub fourcc: FourCC,
+    /// Stream name (`STNM`).
+    pub name: Option<String>,
+    /// SI units (`SIUN`) or display units (`UNIT`).
+    pub units: Option<Vec<String>>,
+    /// Sample rate in Hz fitted from `TSMP`,
+    /// `None` if fewer than two payloads log `TSMP`.
+    pub rate: Option<f64>,
+    /// Time offset (`TIMO`) subtracted from all samples.
+    pub offset: Duration,
+    pub samples: Vec<TimedSample>,
+    /// Index of the source `DEVC` and the corresponding range
+    /// in `samples`, for each payload.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) payloads: Vec<(usize, Range<usize>)>,
+}
+
+/// Timing and values for the data stream in a single `STRM`.
+struct Payload {
+    /// Index of the source `DEVC`.
+    devc: usize,
+    /// `DEVC` start, relative to video start, in seconds.
+    start: f64,
+    /// `DEVC` duration in seconds.
+    duration: f64,
+    /// Total samples delivered, including this payload.
+    tsmp: Option<u32>,
+    /// Microsecond timestamp for first sample.
+    stmp: Option<f64>,
+    /// Time offset in seconds.
+    timo: Option<f64>,
+    values: Vec<Vec<f64>>,
+}
+
+impl Payload {
+    fn new(index: usize, devc: &Stream, strm: &Stream, data: &Stream) -> Option<Self> {
+        let time = devc.time.as_ref()?;
+
+        let first = |fourcc: &FourCC| strm.find(fourcc)
+            .and_then(|s| s.first_value())
+            .and_then(|v| Option::<f64>::from(v));
+
+        let scale = strm.find(&FourCC::SCAL)
+            .and_then(|s| s.to_f64())
+            .unwrap_or_default();
+
+        let values = data.to_vec_f64()?
+            .into_iter()
+            .map(|sample| match scale.len() {
+                0 => sample,
+                // Single scale value for all values in sample
+                1 => sample.iter().map(|v| v / scale[0]).collect(),
+                // One scale value per value in sample
+                n if n == sample.len() => sample.iter()
+                    .zip(scale.iter())
+                    .map(|(v, s)| v / s)
+                    .collect(),
+                _ => sample,
+            })
+            .collect();
+
+        Some(Self {
+            devc: index,
+            start: time.relative.as_seconds_f64(),
+            duration: time.duration.as_seconds_f64(),
+            tsmp: strm.find(&FourCC::TSMP)
+                .and_then(|s| s.first_value())
+                .and_then(|v| v.into()),
+            stmp: first(&FourCC::STMP),
+            // TIMO may be logged at DEVC level for some devices
+            timo: first(&FourCC::TIMO).or_else(|| devc.find(&FourCC::TIMO)
+                .and_then(|s| s.first_value())
+                .and_then(|v| Option::<f64>::from(v))),
+            values,
+        })
+    }
+
+    /// Number of samples delivered before this payload.
+    fn delivered(&self) -> Option<f64> {
+        self.tsmp.map(|n| n as f64 - self.values.len() as f64)
+    }
+}
+
+/// Least squares fit of samples delivered (`y`) against time (`x`).
+/// Returns `(rate, intercept)`, i.e. `y = intercept + rate * x`.
+fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (sxy, sxx) = points.iter()
+        .fold((0., 0.), |(sxy, sxx), (x, y)| (
+            sxy + (x - mean_x) * (y - mean_y),
+            sxx + (x - mean_x).powi(2)
+        ));
+    if sxx == 0. || sxy <= 0. {
+        return None
+    }
+    let rate = sxy / sxx;
+    Some((rate, mean_y - rate * mean_x))
+}
+
+impl TimedSamples {
+    /// Returns timed samples for data streams with specified Four CC
+    /// in `devc_streams`, one `TimedSamples` per device.
+    /// `DEVC` streams without timestamps (e.g. raw GPMF)
+    /// are ignored.
+    pub fn new(devc_streams: &[Stream], fourcc: &FourCC) -> Vec<Self> {
+        Self::collect(devc_streams, |strm| strm.find(fourcc))
+    }
+
+    /// Returns timed samples for data streams with specified `DataType`
+    /// (i.e. stream name, `STNM`) in `devc_streams`,
+    /// one `TimedSamples` per device.
+    /// The data stream is the last stream in each `STRM`.
+    /// `DEVC` streams without timestamps (e.g. raw GPMF)
+    /// are ignored.
+    pub fn from_data_type(devc_streams: &[Stream], data_type: &DataType) -> Vec<Self> {
+        Self::collect(devc_streams, |strm| {
+            match strm.name().as_deref() == Some(data_type.to_str()) {
+                true => strm.last_stream(),
+                false => None,
+            }
+        })
+    }
+
+    /// Collects payloads per device, for data streams
+    /// returned by `data` for each `STRM`.
+    fn collect<'a, F>(devc_streams: &'a [Stream], data: F) -> Vec<Self>
+    where
+        F: Fn(&'a Stream) -> Option<&'a Stream>
+    {
+        // Payloads in logged order, per device
+        let mut devices: Vec<(Self, Vec<Payload>)> = Vec::new();
+
+        for (i, devc) in devc_streams.iter().enumerate() {
+            let device = devc.device_name();
+            let StreamType::Nested(children) = &devc.streams else {
+                continue
+            };
+            for strm in children.iter().filter(|s| s.has_fourcc(&FourCC::STRM)) {
+                let Some(data) = data(strm) else {
+                    continue
+                };
+                let Some(payload) = Payload::new(i, devc, strm, data) else {
+                    continue
+                };
+
+                let idx = match devices.iter().position(|(t, _)| t.device == device) {
+                    Some(i) => i,
+                    None => {
+                        let units = strm.find(&FourCC::SIUN)
+                            .or_else(|| strm.find(&FourCC::UNIT))
+                            .and_then(|s| s.values())
+                            .map(|v| v.iter().filter_map(|s| s.into()).collect());
+                        devices.push((Self {
+                            device: device.to_owned(),
+                            fourcc: data.fourcc().to_owned(),
+                            name: strm.name(),
+                            units,
+                            ..Self::default()
+                        }, Vec::new()));
+                        devices.len() - 1
+                    }
+                };
+                devices[idx].1.push(payload);
+            }
+        }
+
+        devices.into_iter()
+            .map(|(timed, payloads)| timed.with_payloads(payloads))
+            .collect()
+    }
+
+    /// Derives sample times for all payloads.
+    fn with_payloads(self, payloads: Vec<Payload>) -> Self {
+        // TSMP fitted against payload start: samples delivered before
+        // payload vs time, slope is sample rate.
+        let points: Vec<(f64, f64)> = payloads.iter()
+            .filter_map(|p| p.delivered().map(|n| (p.start, n)))
+            .collect();
+        let fitted = fit(&points);
+
+        let timo = payloads.iter()
+            .find_map(|p| p.timo)
+            .unwrap_or(0.);
+
+        // STMP is only used if logged for all payloads
+        let stmp: Option<Vec<f64>> = payloads.iter()
+            .map(|p| p.stmp)
+            .collect();
+
+        let mut samples: Vec<TimedSample> = Vec::new();
+        let mut ranges: Vec<(usize, Range<usize>)> = Vec::new();
+
+        for (k, payload) in payloads.iter().enumerate() {
+            let count = payload.values.len();
+            if count == 0 {
+                continue
+            }
+
+            // Payload start and sample duration in seconds
+            let (start, step) = match (&stmp, fitted, payload.delivered()) {
+                (Some(stmp), _, _) => {
+                    // Anchor first STMP to fitted (or MP4) time for first payload
+                    let first = payloads[0].delivered()
+                        .zip(fitted)
+                        .map(|(n, (rate, b))| (n - b) / rate)
+                        .unwrap_or(payloads[0].start);
+                    let start = first + (stmp[k] - stmp[0]) / 1_000_000.;
+                    let step = match (stmp.get(k + 1), fitted) {
+                        (Some(next), _) => (next - stmp[k]) / 1_000_000. / count as f64,
+                        (None, Some((rate, _))) => 1. / rate,
+                        (None, None) => payload.duration / count as f64,
+                    };
+                    (start, step)
+                },
+                (None, Some((rate, b)), Some(n)) => ((n - b) / rate, 1. / rate),
+                _ => (payload.start, payload.duration / count as f64),
+            };
+
+            let first = samples.len();
+            samples.extend(payload.values.iter()
+                .enumerate()
+                .map(|(i, values)| TimedSample {
+                    time: Duration::seconds_f64(start + i as f64 * step - timo),
+                    values: values.to_owned(),
+                }));
+            ranges.push((payload.devc, first..samples.len()));
+        }
+
+        Self {
+            rate: fitted.map(|(rate, _)| rate),
+            offset: Duration::seconds_f64(timo),
+            samples,
+            payloads: ranges,
+            ..self
+        }
+    }
+
+    /// Returns samples for the payload in the `DEVC` at `index`
+    /// in the streams the samples were derived from.
+    pub(crate) fn payload(&self, index: usize) -> Option<&[TimedSample]> {
+        self.payloads.iter()
+            .find(|(devc, _)| *devc == index)
+            .and_then(|(_, range)| self.samples.get(range.to_owned()))
+    }
+
+    /// Returns number of samples.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TimedSample> {
+        self.samples.iter()
+    }
+
+    /// Returns sample times relative to video start
+    /// as fractional seconds.
+    pub fn times(&self) -> Vec<f64> {
+        self.samples.iter()
+            .map(|s| s.time.as_seconds_f64())
+            .collect()
+    }
+
+    /// Returns samples with a time within `start` (inclusive)
+    /// and `end` (exclusive), relative to video start.
+    pub fn between(&self, start: Duration, end: Duration) -> &[TimedSample] {
+        let from = self.samples.partition_point(|s| s.time < start);
+        let to = self.samples.partition_point(|s| s.time < end);
+        &self.samples[from..to.max(from)]
+    }
+
+    /// Returns first sample time relative to video start.
+    pub fn start(&self) -> Option<Duration> {
+        self.samples.first().map(|s| s.time)
+    }
+
+    /// Returns last sample time relative to video start.
+    pub fn end(&self) -> Option<Duration> {
+        self.samples.last().map(|s| s.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::TimedSamples;
+    use crate::{FourCC, Stream};
+    use crate::gpmf::Value;
+    use crate::tests::{container, timed, uint32, values};
+
+    /// `DEVC` at `start` seconds with duration 1 s and
+    /// `count` `ACCL` samples, preceded by `timing` streams.
+    fn devc(start: f64, count: i16, timing: Vec<Stream>) -> Stream {
+        devc_with_duration(start, 1.0, count, timing)
+    }
+
+    fn devc_with_duration(start: f64, duration: f64, count: i16, timing: Vec<Stream>) -> Stream {
+        let accl = values("ACCL", b's', 2, (0..count).map(|i| Value::Sint16(vec![i])).collect());
+        let strm = container("STRM", timing.into_iter().chain([accl]).collect());
+        timed(container("DEVC", vec![strm]), start, duration)
+    }
+
+    fn stmp(microseconds: u64) -> Stream {
+        values("STMP", b'J', 8, vec![Value::Uint64(vec![microseconds])])
+    }
+
+    fn accl(streams: &[Stream]) -> TimedSamples {
+        TimedSamples::new(streams, &FourCC::ACCL).into_iter().next().unwrap()
+    }
+
+    fn assert_times(timed: &TimedSamples, expected: &[f64]) {
+        let times = timed.times();
+        assert_eq!(times.len(), expected.len(), "{times:?}");
+        for (t, e) in times.iter().zip(expected) {
+            assert!((t - e).abs() < 1e-6, "{times:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn timed_even() {
+        // No TSMP or STMP, spread across DEVC duration
+        let timed = accl(&[devc(0., 4, vec![]), devc(1., 2, vec![])]);
+
+        assert_eq!(timed.rate, None);
+        assert_times(&timed, &[0., 0.25, 0.5, 0.75, 1., 1.5]);
+        assert_eq!(timed.samples[5].values, vec![1.]);
+    }
+
+    #[test]
+    fn timed_tsmp() {
+        // 10 Hz, DEVC duration would spread samples 0.08 s apart
+        let timed = accl(&[
+            devc_with_duration(0., 0.8, 10, vec![uint32("TSMP", 10)]),
+            devc_with_duration(1., 0.8, 10, vec![uint32("TSMP", 20)]),
+            devc_with_duration(2., 0.8, 10, vec![uint32("TSMP", 30)]),
+        ]);
+
+        // Fitted: delivered = 10 * t
+        assert!((timed.rate.unwrap() - 10.).abs() < 1e-9);
+        let expected: Vec<f64> = (0..30).map(|i| i as f64 * 0.1).collect();
+        assert_times(&timed, &expected);
+        assert_eq!(timed.payload(1).map(|s| s.len()), Some(10));
+        assert!((timed.payload(1).unwrap()[0].time.as_seconds_f64() - 1.0).abs() < 1e-6);
+        assert_eq!(timed.payload(3), None);
+    }
+
+    #[test]
+    fn timed_stmp() {
+        // STMP spacing 1.01 s, TSMP rate 10 Hz
+        let timed = accl(&[
+            devc(0., 10, vec![uint32("TSMP", 10), stmp(5_000_000)]),
+            devc(1., 10, vec![uint32("TSMP", 20), stmp(6_010_000)]),
+            devc(2., 10, vec![uint32("TSMP", 30), stmp(7_020_000)]),
+        ]);
+
+        let expected: Vec<f64> = (0..10).map(|i| i as f64 * 0.101)
+            .chain((0..10).map(|i| 1.01 + i as f64 * 0.101))
+            // Last payload, step from fitted rate
+            .chain((0..10).map(|i| 2.02 + i as f64 * 0.1))
+            .collect();
+        assert_times(&timed, &expected);
+    }
+
+    #[test]
+    fn timed_stmp_only() {
+        // Anchored to first DEVC start
+        let timed = accl(&[
+            devc(0.5, 2, vec![stmp(1_000_000)]),
+            devc(1.5, 2, vec![stmp(2_200_000)]),
+        ]);
+
+        assert_eq!(timed.rate, None);
+        assert_times(&timed, &[0.5, 1.1, 1.7, 2.2]);
+    }
+
+    #[test]
+    fn timed_timo() {
+        let timo = || values("TIMO", b'f', 4, vec![Value::Float32(vec![0.25])]);
+        let timed = accl(&[devc(1., 4, vec![timo()])]);
+
+        assert_eq!(timed.offset, Duration::seconds_f64(0.25));
+        assert_times(&timed, &[0.75, 1., 1.25, 1.5]);
+    }
+
+    #[test]
+    fn timed_untimed() {
+        // DEVC without timestamp
+        let devc = container("DEVC", vec![container("STRM", vec![
+            values("ACCL", b's', 2, vec![Value::Sint16(vec![1])]),
+        ])]);
+        assert!(TimedSamples::new(&[devc], &FourCC::ACCL).is_empty());
+    }
+}
@@ -4,6 +4,7 @@ use mp4iter::Sample;
 use time::{self, Duration};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Timestamp containing relative time in milliseconds from
 /// video start and the "duration" (i.e. time until write of next GPMF chunk)
 /// of the DEVC the current stream belongs to.
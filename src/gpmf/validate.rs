@@ -0,0 +1,332 @@
+//! Structural validation of parsed GPMF against the GPMF specification,
+//! e.g. for checking data from new devices or firmware before trusting it.
+//! See `Gpmf::validate()`.
+
+use std::{collections::HashMap, fmt::Display};
+
+use super::{FourCC, Header, Location, Stream, StreamType, Value};
+
+/// Severity of a `ValidationIssue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Allowed by the specification, but may be of interest,
+    /// e.g. undocumented Four CC or empty payloads.
+    Info,
+    /// Data may be interpreted incorrectly.
+    Warning,
+    /// Violates the specification.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Info => write!(f, "INFO"),
+            Self::Warning => write!(f, "WARNING"),
+            Self::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// Structural problem found in GPMF data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// Path to the affected stream,
+    /// e.g. `DEVC #2 > STRM #3 > SCAL`.
+    pub path: String,
+    /// Byte location of the affected stream, if known.
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.severity, self.path)?;
+        if let Some(loc) = &self.location {
+            write!(f, " @{}", loc.offset)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl ValidationIssue {
+    fn new(severity: Severity, path: &str, stream: &Stream, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            path: path.to_owned(),
+            location: stream.location,
+            message: message.into(),
+        }
+    }
+}
+
+/// Validates top-level streams, usually `DEVC`.
+pub(crate) fn validate(streams: &[Stream]) -> Vec<ValidationIssue> {
+    let mut issues: Vec<ValidationIssue> = Vec::new();
+
+    // Last TSMP per device and data stream
+    let mut tsmp: HashMap<(Option<String>, String), u32> = HashMap::new();
+
+    for (i, devc) in streams.iter().enumerate() {
+        let path = format!("{} #{}", devc.fourcc().to_str(), i + 1);
+        validate_stream(devc, &path, &mut issues);
+
+        if let StreamType::Nested(children) = &devc.streams {
+            let device = devc.device_name();
+            for (j, strm) in children.iter().enumerate() {
+                if strm.has_fourcc(&FourCC::STRM) {
+                    let path = format!("{path} > STRM #{}", j + 1);
+                    validate_strm(strm, &path, &device, &mut tsmp, &mut issues);
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+/// Checks that apply to any stream.
+/// Recurses into child streams.
+fn validate_stream(stream: &Stream, path: &str, issues: &mut Vec<ValidationIssue>) {
+    if let FourCC::Other(fourcc) = stream.fourcc() {
+        issues.push(ValidationIssue::new(Severity::Info, path, stream,
+            format!("undocumented Four CC '{fourcc}'")
+        ));
+    }
+
+    let children = match &stream.streams {
+        StreamType::Values(values) => {
+            if values.iter().any(|v| matches!(v, Value::Invalid)) {
+                issues.push(ValidationIssue::new(Severity::Error, path, stream,
+                    "contains corrupt values"
+                ));
+            }
+            return
+        },
+        StreamType::Nested(children) => children,
+    };
+
+    // Complex type definition for following siblings
+    let mut complex: Option<String> = None;
+    // Total size of contents, only known if all children have a location
+    let mut total: Option<u32> = Some(0);
+
+    for (i, child) in children.iter().enumerate() {
+        let child_path = format!("{path} > {} #{}", child.fourcc().to_str(), i + 1);
+
+        // Streams must start at 32-bit aligned offsets
+        if let (Some(parent), Some(loc)) = (stream.location, child.location) {
+            if loc.offset.saturating_sub(parent.offset) % 4 != 0 {
+                issues.push(ValidationIssue::new(Severity::Error, &child_path, child,
+                    "not 32-bit aligned"
+                ));
+            }
+        }
+
+        // Original size as parsed, since the header of
+        // a decompressed stream no longer matches the source
+        total = total.zip(child.location)
+            .map(|(t, loc)| t + loc.size);
+
+        if child.has_fourcc(&FourCC::TYPE) {
+            complex = child.first_value().and_then(|v| v.into());
+        }
+
+        if child.header.basetype == b'?' {
+            match &complex {
+                Some(types) => {
+                    let size: u32 = types.bytes()
+                        .map(|t| Header::baselen(&t) as u32)
+                        .sum();
+                    if size != child.header.basesize as u32 {
+                        issues.push(ValidationIssue::new(Severity::Error, &child_path, child,
+                            format!("TYPE '{types}' describes {size} bytes, structure size is {} bytes", child.header.basesize)
+                        ));
+                    }
+                },
+                None => issues.push(ValidationIssue::new(Severity::Error, &child_path, child,
+                    "complex type without preceding TYPE"
+                )),
+            }
+        }
+
+        validate_stream(child, &child_path, issues);
+    }
+
+    if let Some(total) = total && total != stream.size(false) {
+        issues.push(ValidationIssue::new(Severity::Error, path, stream,
+            format!("container size {} bytes does not match aligned size of contents {total} bytes", stream.size(false))
+        ));
+    }
+}
+
+/// Checks for `STRM` metadata (`SCAL`, `TSMP`, `EMPT`)
+/// against the data stream, which is the last stream in `STRM`.
+fn validate_strm(
+    strm: &Stream,
+    path: &str,
+    device: &Option<String>,
+    tsmp: &mut HashMap<(Option<String>, String), u32>,
+    issues: &mut Vec<ValidationIssue>
+) {
+    let Some(data) = strm.last_stream() else {
+        issues.push(ValidationIssue::new(Severity::Warning, path, strm, "empty STRM"));
+        return
+    };
+
+    let data_path = format!("{path} > {}", data.fourcc().to_str());
+
+    // Number of values per sample
+    let width = match data.header.basetype {
+        b'?' => strm.find(&FourCC::TYPE)
+            .and_then(|t| t.first_value())
+            .and_then(|v| Into::<Option<String>>::into(v))
+            .map(|t| t.len() as u32),
+        // Strings, Four CC, dates, UUID
+        b'c' | b'u' | b'F' | b'U' | b'G' | 0 => None,
+        bt => Some(data.header.basesize as u32 / Header::baselen(&bt) as u32),
+    };
+
+    if let (Some(width), Some(scal)) = (width, strm.find(&FourCC::SCAL)) {
+        let count: usize = scal.to_vec_f64()
+            .map(|v| v.iter().map(|s| s.len()).sum())
+            .unwrap_or(0);
+        if count != 1 && count != width as usize {
+            issues.push(ValidationIssue::new(Severity::Error, &format!("{path} > SCAL"), scal,
+                format!("{count} scale values for {} with {width} values per sample", data.fourcc().to_str())
+            ));
+        }
+    }
+
+    if let Some(stream) = strm.find(&FourCC::TSMP) {
+        let count: Option<u32> = stream.first_value().and_then(|v| v.into());
+        if let Some(count) = count {
+            let key = (device.to_owned(), data.fourcc().to_str().to_owned());
+            if let Some(prev) = tsmp.insert(key, count) {
+                if count < prev {
+                    issues.push(ValidationIssue::new(Severity::Error, &format!("{path} > TSMP"), stream,
+                        format!("total samples {count} less than previous {prev} for {}", data.fourcc().to_str())
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(stream) = strm.find(&FourCC::EMPT) {
+        let count: Option<u32> = stream.first_value().and_then(|v| v.into());
+        match count {
+            Some(n) if n > data.header.repeats as u32 => issues.push(
+                ValidationIssue::new(Severity::Error, &format!("{path} > EMPT"), stream,
+                    format!("{n} empty payloads exceeds {} samples in {data_path}", data.header.repeats)
+                )
+            ),
+            Some(n) if n > 0 => issues.push(
+                ValidationIssue::new(Severity::Info, &format!("{path} > EMPT"), stream,
+                    format!("{n} empty payloads in {data_path}")
+                )
+            ),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{validate, Severity, ValidationIssue};
+    use crate::gpmf::{ParseOptions, Stream, StreamType};
+    use crate::tests::{klv, klv_container};
+
+    fn devc(tsmp: u32) -> Vec<u8> {
+        let gyro: Vec<u8> = [1_i16, 2, 3, -1, -2, -3].iter().flat_map(|n| n.to_be_bytes()).collect();
+        klv_container(b"DEVC", &[
+            klv(b"DVID", b'L', 4, 1, &1_u32.to_be_bytes()),
+            klv(b"DVNM", b'c', 6, 1, b"Camera"),
+            klv_container(b"STRM", &[
+                klv(b"STNM", b'c', 4, 1, b"Gyro"),
+                klv(b"SCAL", b's', 2, 1, &100_i16.to_be_bytes()),
+                klv(b"TSMP", b'L', 4, 1, &tsmp.to_be_bytes()),
+                klv(b"GYRO", b's', 6, 2, &gyro),
+            ]),
+        ])
+    }
+
+    fn parse(bytes: &[u8]) -> Vec<Stream> {
+        Stream::new(&mut Cursor::new(bytes), bytes.len()).unwrap()
+    }
+
+    fn has_issue(issues: &[ValidationIssue], severity: Severity, path: &str, message: &str) -> bool {
+        issues.iter().any(|i| i.severity == severity && i.path == path && i.message.contains(message))
+    }
+
+    #[test]
+    fn validate_valid() {
+        let bytes = [devc(2), devc(4)].concat();
+        let streams = parse(&bytes);
+        assert_eq!(validate(&streams), vec![]);
+    }
+
+    #[test]
+    fn validate_container_size() {
+        // Container size includes 8 bytes of trailing zero padding,
+        // which is not a part of any child stream
+        let data = devc(2)[8..].to_vec();
+        let len = data.len() as u16 + 8;
+        let bytes = klv(b"DEVC", 0, 1, len, &[data, vec![0; 8]].concat());
+
+        let issues = validate(&parse(&bytes));
+
+        assert_eq!(issues.len(), 1);
+        assert!(has_issue(&issues, Severity::Error, "DEVC #1",
+            &format!("container size {len} bytes does not match aligned size of contents {} bytes", len - 8)
+        ));
+        assert_eq!(issues[0].location.map(|l| l.offset), Some(0));
+    }
+
+    #[test]
+    fn validate_alignment() {
+        let mut streams = parse(&devc(2));
+        if let StreamType::Nested(children) = &mut streams[0].streams {
+            children[1].location.as_mut().unwrap().offset += 2;
+        }
+
+        let issues = validate(&streams);
+
+        assert_eq!(issues.len(), 1);
+        assert!(has_issue(&issues, Severity::Error, "DEVC #1 > DVNM #2", "not 32-bit aligned"));
+    }
+
+    #[test]
+    fn validate_corrupt() {
+        // Complex type without preceding TYPE can not be read
+        let bytes = klv_container(b"DEVC", &[
+            klv_container(b"STRM", &[
+                klv(b"STNM", b'c', 4, 1, b"Accl"),
+                klv(b"CPLX", b'?', 8, 1, &[0, 0, 0, 1, 0, 0, 0, 2]),
+            ]),
+        ]);
+        assert!(Stream::new(&mut Cursor::new(&bytes), bytes.len()).is_err());
+
+        let mut diagnostics = Vec::new();
+        let streams = Stream::new_with_options(
+            &mut Cursor::new(&bytes),
+            bytes.len(),
+            &ParseOptions::lenient(),
+            &mut diagnostics
+        ).unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].offset, 28);
+        assert!(diagnostics[0].message.starts_with("failed to read value, stored as invalid"));
+
+        let issues = validate(&streams);
+        let path = "DEVC #1 > STRM #1 > CPLX #2";
+
+        assert!(has_issue(&issues, Severity::Info, path, "undocumented Four CC 'CPLX'"));
+        assert!(has_issue(&issues, Severity::Error, path, "complex type without preceding TYPE"));
+        assert!(has_issue(&issues, Severity::Error, path, "contains corrupt values"));
+        assert_eq!(issues.iter().filter(|i| i.severity == Severity::Error).count(), 2);
+    }
+}
@@ -1,6 +1,6 @@
 //! Core structure for GPMF raw data values.
 
-use std::{io::{BufRead, Read, Seek}, time::{SystemTime, UNIX_EPOCH}};
+use std::{io::{BufRead, Read, Seek, Write}, time::{SystemTime, UNIX_EPOCH}};
 
 use binrw::{BinRead, BinReaderExt, BinResult};
 use time::{Duration, PrimitiveDateTime, format_description};
@@ -19,12 +19,14 @@ use crate::{DeviceId, FourCC, GpmfError};
 ///
 /// Notes:
 /// - Type `35`/`#` contains "Huffman compression STRM payloads. 4-CC <type><size><rpt> <data ...> is compressed as 4-CC '#'<new size/rpt> <type><size><rpt> <compressed data ...>" (see above GitHub repo).
-/// It is currently parsed into `Vec<u8>`, but no further decoding or processing is implemented.
+/// These are decoded into the uncompressed type when parsed into a `Stream`, so `Value::Compressed`
+/// is only returned when reading a single value directly.
 /// - Strings except `Value::Utf8()` variant map to ISO8859-1 as a single-byte (0-255) extension of ascii. I.e. `String::from_utf8(Vec<u8>)` would be incorrect or fail for values above 127. See <https://github.com/gopro/gpmf-parser/issues/143#issuecomment-952125684>. `u8 as char` is used as a workaround to produce a valid UTF-8 string.
 ///
 /// For the original C source, see:
 /// <https://github.com/gopro/gpmf-parser/blob/420930426c00a2ef3158847f967aed2acb2b06c1/GPMF_common.h>
 #[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     /// Ascii: c/99, single byte 'c' style ASCII character string, char.
     /// Optionally NULL terminated. Size/repeat sets the length.
@@ -193,7 +195,7 @@ impl Value {
             b'U' => Self::Datetime(Self::from_iso8859_1(reader, header)?),
             // UUID has total len 16, uint8_t, [u8; 16]
             b'G' => Self::Uuid(Self::read::<u8, R>(reader, header)?),
-            // Huffman compression STRM payloads (just raw data, no decode)
+            // Huffman compression STRM payloads (raw data, decoded in `Stream::new()`)
             b'#' => Self::Compressed(Self::read::<u8, R>(reader, header)?),
             // Complex basetype, a dynamic, composite basetype that combines other basetypes.
             b'?' => {
@@ -229,6 +231,65 @@ impl Value {
         Ok(values)
     }
 
+    /// Writes `Value` as big endian GPMF bytes. The inverse of `Value::new()`.
+    ///
+    /// Exactly `header.basesize` bytes are written. Shorter values,
+    /// such as strings that had trailing `null` characters removed
+    /// when parsed, are zero padded.
+    ///
+    /// > IMPORTANT: As for `Value::new()`, 32-bit alignment
+    /// > is not handled here, but per `Stream`.
+    pub fn write<W: Write>(
+        &self,
+        writer: &mut W,
+        header: &Header,
+        complextype: Option<&str>,
+    ) -> Result<(), GpmfError> {
+        let mut buf: Vec<u8> = Vec::with_capacity(header.basesize as usize);
+
+        match self {
+            // ISO8859-1, see `Value::from_iso8859_1()`
+            Self::String(s)
+            | Self::FourCC(s)
+            | Self::Datetime(s) => buf.extend(s.chars().map(|c| c as u32 as u8)),
+            Self::Utf8(s) => buf.extend(s.as_bytes()),
+            Self::Uuid(v) | Self::Uint8(v) | Self::Compressed(v) => buf.extend(v),
+            Self::Sint8(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Sint16(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Uint16(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Sint32(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Uint32(v) | Self::Qint32(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Float32(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Sint64(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Uint64(v) | Self::Qint64(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Float64(v) => buf.extend(v.iter().flat_map(|n| n.to_be_bytes())),
+            Self::Complex(values) => {
+                let types = complextype.ok_or(GpmfError::MissingComplexType)?;
+                if types.len() != values.len() {
+                    return Err(GpmfError::EncodeError(format!(
+                        "{} complex type '{types}' does not match {} values",
+                        header.fourcc.to_str(),
+                        values.len()
+                    )))
+                }
+                for (t, value) in types.as_bytes().iter().zip(values.iter()) {
+                    // Convert header with type `?` to header with specific type.
+                    value.write(&mut buf, &header.convert(t), None)?;
+                }
+            },
+            Self::Nested | Self::Empty => (),
+            Self::Invalid => return Err(GpmfError::EncodeError(format!(
+                "{} contains invalid value",
+                header.fourcc.to_str()
+            ))),
+        }
+
+        buf.resize(header.basesize as usize, 0);
+        writer.write_all(&buf)?;
+
+        Ok(())
+    }
+
     pub fn debug(&self) -> &dyn std::fmt::Debug {
         match self {
             Self::String(v) => v,
@@ -48,9 +48,9 @@ mod tests;
 mod types;
 
 pub use constants::*;
-pub use content_types::{DataType, GoProPoint, Gps};
+pub use content_types::{AltitudeReference, CleanOptions, CleanReport, CsvColumn, CsvOptions, CsvRow, DataType, GeoJsonKind, GoProPoint, Gps, GpsStats, Rejection, Simplification, SimplifyOptions, Sport, StatsOptions, ToCsv};
 pub use content_types::{ImuOrientation, Imu, ImuQuantifier, ImuSample, ImuType};
 pub use errors::GpmfError;
-pub use gopro::{DeviceId, DeviceInfo, DeviceName, GoProFile, GoProMultiSession, GoProSession};
-pub use gpmf::{FourCC, Gpmf, Stream, StreamType, Timestamp};
+pub use gopro::{DeviceId, DeviceInfo, DeviceName, GoProFile, GoProMultiSession, GoProSession, Smpte, Timecode};
+pub use gpmf::{Diagnostic, FourCC, FrameMap, Gpmf, GpmfIter, Interpolation, Location, ParseOptions, Resampled, Selection, Selector, Severity, Stream, StreamType, TimeModel, TimeSource, TimedSample, TimedSamples, Timestamp, ValidationIssue};
 pub use types::{Gumi, Muid, Cpid};
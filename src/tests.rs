@@ -0,0 +1,83 @@
+//! Shared helpers for unit tests, for building GPMF streams by hand.
+
+use time::Duration;
+
+use crate::{FourCC, Stream, StreamType, Timestamp};
+use crate::gpmf::{Header, Value};
+
+/// Terminal stream with `basesize` bytes per value.
+pub(crate) fn values(fourcc: &str, basetype: u8, basesize: u8, values: Vec<Value>) -> Stream {
+    Stream {
+        header: Header::from_parts(FourCC::from_str(fourcc), basetype, basesize, values.len() as u16),
+        streams: StreamType::Values(values),
+        time: None,
+        location: None,
+    }
+}
+
+/// Container stream, with size derived from child headers.
+pub(crate) fn container(fourcc: &str, children: Vec<Stream>) -> Stream {
+    let len: u32 = children.iter()
+        .map(|s| 8 + s.size(true))
+        .sum();
+    Stream {
+        header: Header::nested(&FourCC::from_str(fourcc), len as usize).unwrap(),
+        streams: StreamType::Nested(Box::new(children)),
+        time: None,
+        location: None,
+    }
+}
+
+/// Sets `DEVC` timestamp in seconds.
+pub(crate) fn timed(stream: Stream, relative: f64, duration: f64) -> Stream {
+    stream.with_time(&Timestamp {
+        relative: Duration::seconds_f64(relative),
+        duration: Duration::seconds_f64(duration),
+    })
+}
+
+/// String stream, e.g. `STNM`.
+pub(crate) fn string(fourcc: &str, value: &str) -> Stream {
+    values(fourcc, b'c', value.len() as u8, vec![Value::String(value.to_owned())])
+}
+
+/// Single `u32` stream, e.g. `TSMP`.
+pub(crate) fn uint32(fourcc: &str, value: u32) -> Stream {
+    values(fourcc, b'L', 4, vec![Value::Uint32(vec![value])])
+}
+
+/// `GPS5` `STRM` with scale `[1e7, 1e7, 1000, 1000, 100]`,
+/// 3D lock and DOP 1.5. `GPSU` and `TSMP` are left out if `None`.
+pub(crate) fn gps5_strm(gpsu: Option<&str>, tsmp: Option<u32>, gps5: Vec<Vec<i32>>) -> Stream {
+    let scale = [10_000_000, 10_000_000, 1_000, 1_000, 100];
+    let mut children = vec![
+        string("STNM", "GPS (Lat., Long., Alt., 2D speed, 3D speed)"),
+        values("SCAL", b'l', 4, scale.iter().map(|s| Value::Sint32(vec![*s])).collect()),
+    ];
+    if let Some(tsmp) = tsmp {
+        children.push(uint32("TSMP", tsmp));
+    }
+    if let Some(gpsu) = gpsu {
+        children.push(values("GPSU", b'U', 16, vec![Value::Datetime(gpsu.to_owned())]));
+    }
+    children.push(uint32("GPSF", 3));
+    children.push(values("GPSP", b'S', 2, vec![Value::Uint16(vec![150])]));
+    children.push(values("GPS5", b'l', 20, gps5.into_iter().map(Value::Sint32).collect()));
+    container("STRM", children)
+}
+
+/// Raw KLV with zero padding for 32-bit alignment.
+pub(crate) fn klv(fourcc: &[u8; 4], basetype: u8, basesize: u8, repeats: u16, data: &[u8]) -> Vec<u8> {
+    let mut buf = fourcc.to_vec();
+    buf.extend([basetype, basesize]);
+    buf.extend(repeats.to_be_bytes());
+    buf.extend(data);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+    buf
+}
+
+/// Raw container KLV.
+pub(crate) fn klv_container(fourcc: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+    let data = children.concat();
+    klv(fourcc, 0, 1, data.len() as u16, &data)
+}